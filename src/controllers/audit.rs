@@ -0,0 +1,159 @@
+use super::auth::{random_hex, CurrentUser, Manager};
+use super::error::ApiError;
+use super::params::{optional_date_range, pagination, Page, QueryDate};
+use crate::dao::{self, audit::AuditContext};
+use crate::models::{audit, sea_orm_active_enums::AuditEntity};
+use rocket::{
+    async_trait,
+    fairing::{Fairing, Info, Kind},
+    get,
+    request::{FromRequest, Outcome},
+    serde::json::Json,
+    FromForm, Request, Response, State,
+};
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    okapi::openapi3::{Object, Parameter, ParameterValue},
+    openapi,
+    request::{OpenApiFromRequest, RequestHeaderInput},
+};
+use schemars::JsonSchema;
+use sea_orm::{ActiveEnum, DatabaseConnection};
+
+const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+struct RequestId(String);
+
+/// The id of a request: the client's `X-Request-Id` if it sent a usable one,
+/// else a random one.
+fn request_id<'r>(request: &'r Request<'_>) -> &'r str {
+    let RequestId(id) = request.local_cache(|| {
+        let id = request
+            .headers()
+            .get_one(REQUEST_ID_HEADER)
+            .map(str::trim)
+            .filter(|id| {
+                !id.is_empty()
+                    && id.len() <= 64
+                    && id
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+            });
+        RequestId(id.map_or_else(|| random_hex(16), String::from))
+    });
+    id
+}
+
+/// Sends every response with the `X-Request-Id` of its request, so that
+/// audit entries can be traced back to it.
+pub struct RequestIds;
+
+#[async_trait]
+impl Fairing for RequestIds {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request ids",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        response.set_raw_header(REQUEST_ID_HEADER, request_id(request).to_string());
+    }
+}
+
+/// Audits changes in the name of the logged-in user. Put it after the role
+/// guard, which answers requests without a session.
+#[async_trait]
+impl<'r> FromRequest<'r> for AuditContext {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        CurrentUser::from_request(request)
+            .await
+            .map(|CurrentUser(user)| AuditContext {
+                actor: user.username,
+                request_id: request_id(request).to_string(),
+            })
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for AuditContext {
+    fn from_request_input(
+        gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::Parameter(Parameter {
+            name: String::from(REQUEST_ID_HEADER),
+            location: String::from("header"),
+            description: Some(String::from(
+                "Recorded with the audit entries of the request; one is made up when absent.",
+            )),
+            required: false,
+            deprecated: false,
+            allow_empty_value: false,
+            value: ParameterValue::Schema {
+                style: None,
+                explode: None,
+                allow_reserved: false,
+                schema: gen.json_schema::<String>(),
+                example: None,
+                examples: None,
+            },
+            extensions: Object::default(),
+        }))
+    }
+}
+
+#[derive(FromForm, JsonSchema)]
+pub struct AuditQuery {
+    /// `item`, `batch` or `stock_out`.
+    entity: Option<String>,
+    entity_id: Option<i32>,
+    /// Username of whoever made the changes.
+    actor: Option<String>,
+    /// Changes made on or after this date (YYYY-MM-DD).
+    from: Option<QueryDate>,
+    /// Changes made on or before this date (YYYY-MM-DD).
+    to: Option<QueryDate>,
+    /// 1-based page number.
+    page: Option<usize>,
+    per_page: Option<usize>,
+}
+
+/// Lists audit entries, newest first.
+#[openapi(tag = "audit")]
+#[get("/audit?<query..>")]
+pub async fn get_audit(
+    db: &State<DatabaseConnection>,
+    _user: Manager,
+    query: AuditQuery,
+) -> Result<Json<Page<audit::Model>>, ApiError> {
+    let (page, per_page) = pagination(query.page, query.per_page)?;
+    let entity = query
+        .entity
+        .map(|entity| {
+            AuditEntity::try_from_value(&entity).map_err(|_| {
+                ApiError::BadRequest(format!(
+                    "Unknown entity '{}', expected item, batch or stock_out.",
+                    entity
+                ))
+            })
+        })
+        .transpose()?;
+    let filter = dao::audit::AuditFilter {
+        entity,
+        entity_id: query.entity_id,
+        actor: query.actor,
+        dates: optional_date_range(("from", query.from), ("to", query.to))?,
+    };
+    let (items, total) = dao::audit::get_audit(db, filter, page, per_page).await?;
+
+    Ok(Json(Page {
+        items,
+        total,
+        page,
+        per_page,
+    }))
+}
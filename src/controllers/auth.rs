@@ -0,0 +1,419 @@
+use super::error::{error_responses, ApiError};
+use crate::dao;
+use crate::models::{sea_orm_active_enums::UserRole, user};
+use argon2::{
+    password_hash::{rand_core::OsRng, rand_core::RngCore, SaltString},
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+};
+use rocket::{
+    async_trait, get,
+    http::{Cookie, CookieJar, SameSite, Status},
+    post,
+    request::{FromRequest, Outcome},
+    serde::{json::Json, Deserialize, Serialize},
+    tokio::task::spawn_blocking,
+    Request, State,
+};
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    okapi::openapi3::{Object, Responses, SecurityRequirement, SecurityScheme, SecuritySchemeData},
+    openapi,
+    request::{OpenApiFromRequest, RequestHeaderInput},
+};
+use schemars::JsonSchema;
+use sea_orm::DatabaseConnection;
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+const SESSION_COOKIE: &str = "session";
+const SESSION_HOURS: i64 = 12;
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Hashes a password with Argon2id and a random salt. It is slow on purpose,
+/// so call it off the async executor.
+fn hash_password(password: &str) -> Result<String, ApiError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|_| ApiError::Internal(String::from("Error occurs while hashing the password.")))
+}
+
+/// A hash to verify passwords against when the username is unknown, so that
+/// such logins take as long as those with a wrong password.
+fn dummy_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| hash_password(&random_hex(16)).unwrap_or_default())
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+/// The `session` section of the configuration.
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SessionConfig {
+    /// Whether the session cookie is only sent over HTTPS. Turn it off only
+    /// to serve browsers over plain HTTP on a local machine.
+    #[serde(default = "SessionConfig::default_secure_cookie")]
+    pub secure_cookie: bool,
+}
+
+impl SessionConfig {
+    fn default_secure_cookie() -> bool {
+        true
+    }
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            secure_cookie: SessionConfig::default_secure_cookie(),
+        }
+    }
+}
+
+/// Sessions are looked up by a hash of their token, so a leaked session
+/// table does not let anyone log in.
+fn token_hash(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// `len` random bytes, hex encoded.
+pub fn random_hex(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The session token sent as `Authorization: Bearer <token>`, or else in the
+/// session cookie set on login.
+fn request_token(request: &Request<'_>) -> Option<String> {
+    let bearer = request
+        .headers()
+        .get_one("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string());
+
+    bearer.or_else(|| {
+        request
+            .cookies()
+            .get(SESSION_COOKIE)
+            .map(|cookie| cookie.value().to_string())
+    })
+}
+
+/// The logged-in user. Requests without a valid session get a 401.
+pub struct CurrentUser(pub user::Model);
+
+#[async_trait]
+impl<'r> FromRequest<'r> for CurrentUser {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        // Several guards of one request share the lookup.
+        let user: &Result<user::Model, Status> = request
+            .local_cache_async(async {
+                let token = request_token(request).ok_or(Status::Unauthorized)?;
+                let db = match request.guard::<&State<DatabaseConnection>>().await {
+                    Outcome::Success(db) => db,
+                    _ => return Err(Status::InternalServerError),
+                };
+                match dao::user::get_session_user(db, &token_hash(&token)).await {
+                    Ok(Some(user)) => Ok(user),
+                    Ok(None) => Err(Status::Unauthorized),
+                    Err(err) => Err(ApiError::from(err).status()),
+                }
+            })
+            .await;
+
+        match user {
+            Ok(user) => Outcome::Success(CurrentUser(user.clone())),
+            Err(status) => Outcome::Failure((*status, ())),
+        }
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for CurrentUser {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        let scheme = SecurityScheme {
+            description: Some(String::from(
+                "The token returned by `POST /api/login`. Browsers may send the \
+                session cookie set by the login instead.",
+            )),
+            data: SecuritySchemeData::Http {
+                scheme: String::from("bearer"),
+                bearer_format: None,
+            },
+            extensions: Object::default(),
+        };
+        let mut requirement = SecurityRequirement::new();
+        requirement.insert(String::from("session"), Vec::new());
+
+        Ok(RequestHeaderInput::Security(
+            String::from("session"),
+            scheme,
+            requirement,
+        ))
+    }
+
+    fn get_responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        Ok(error_responses(
+            gen,
+            &[
+                (401, "The request has no valid session."),
+                (403, "The user's role does not allow the request."),
+            ],
+        ))
+    }
+}
+
+/// Declares a guard admitting logged-in users with at least the given role;
+/// others get a 403.
+macro_rules! role_guard {
+    ($(#[$doc:meta])* $name:ident, $role:expr) => {
+        $(#[$doc])*
+        // Most routes only check the role and never read the user.
+        #[allow(dead_code)]
+        pub struct $name(pub user::Model);
+
+        #[async_trait]
+        impl<'r> FromRequest<'r> for $name {
+            type Error = ();
+
+            async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+                match CurrentUser::from_request(request).await {
+                    Outcome::Success(CurrentUser(user)) if user.role >= $role => {
+                        Outcome::Success($name(user))
+                    }
+                    Outcome::Success(_) => Outcome::Failure((Status::Forbidden, ())),
+                    Outcome::Failure(failure) => Outcome::Failure(failure),
+                    Outcome::Forward(forward) => Outcome::Forward(forward),
+                }
+            }
+        }
+
+        impl<'r> OpenApiFromRequest<'r> for $name {
+            fn from_request_input(
+                gen: &mut OpenApiGenerator,
+                name: String,
+                required: bool,
+            ) -> rocket_okapi::Result<RequestHeaderInput> {
+                CurrentUser::from_request_input(gen, name, required)
+            }
+
+            fn get_responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+                CurrentUser::get_responses(gen)
+            }
+        }
+    };
+}
+
+role_guard!(
+    /// A user who may read stock, reports and exports.
+    Viewer,
+    UserRole::Viewer
+);
+role_guard!(
+    /// A user who may also record items and stock movements.
+    Clerk,
+    UserRole::Clerk
+);
+role_guard!(
+    /// A user who may also correct and delete records and manage users.
+    Manager,
+    UserRole::Manager
+);
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Credentials {
+    username: String,
+    password: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Login {
+    /// Bearer token for the `Authorization` header.
+    token: String,
+    expires_at: chrono::NaiveDateTime,
+    user: user::Model,
+}
+
+/// Logs in, returning a session token and also setting it as a cookie.
+#[openapi(tag = "auth")]
+#[post("/login", data = "<credentials>")]
+pub async fn login(
+    db: &State<DatabaseConnection>,
+    config: &State<SessionConfig>,
+    cookies: &CookieJar<'_>,
+    credentials: Json<Credentials>,
+) -> Result<Json<Login>, ApiError> {
+    let Credentials { username, password } = credentials.0;
+    let user = dao::user::get_user_by_username(db, &username).await?;
+
+    let hash = user.as_ref().map(|user| user.password_hash.clone());
+    let verified = spawn_blocking(move || match hash {
+        Some(hash) => verify_password(&password, &hash),
+        None => {
+            verify_password(&password, dummy_hash());
+            false
+        }
+    })
+    .await
+    .unwrap_or(false);
+    let user = match user {
+        Some(user) if verified => user,
+        _ => {
+            return Err(ApiError::Unauthorized(String::from(
+                "Unknown username or wrong password.",
+            )))
+        }
+    };
+
+    let token = random_hex(32);
+    let expires_at = chrono::Local::now().naive_local() + chrono::Duration::hours(SESSION_HOURS);
+    dao::user::insert_session(db, user.id, token_hash(&token), expires_at).await?;
+
+    cookies.add(
+        Cookie::build(SESSION_COOKIE, token.clone())
+            .path("/")
+            .http_only(true)
+            .secure(config.secure_cookie)
+            .same_site(SameSite::Strict)
+            .finish(),
+    );
+
+    Ok(Json(Login {
+        token,
+        expires_at,
+        user,
+    }))
+}
+
+/// Ends the current session.
+#[openapi(tag = "auth")]
+#[post("/logout")]
+pub async fn logout(
+    db: &State<DatabaseConnection>,
+    cookies: &CookieJar<'_>,
+    request_token: RequestToken,
+) -> Result<(), ApiError> {
+    dao::user::delete_session(db, &token_hash(&request_token.0)).await?;
+    cookies.remove(Cookie::named(SESSION_COOKIE));
+
+    Ok(())
+}
+
+/// The token a request was made with, valid or not.
+pub struct RequestToken(String);
+
+#[async_trait]
+impl<'r> FromRequest<'r> for RequestToken {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        match request_token(request) {
+            Some(token) => Outcome::Success(RequestToken(token)),
+            None => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for RequestToken {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::None)
+    }
+}
+
+#[openapi(tag = "auth")]
+#[get("/me")]
+pub async fn get_current_user(user: CurrentUser) -> Json<user::Model> {
+    Json(user.0)
+}
+
+#[openapi(tag = "auth")]
+#[get("/users")]
+pub async fn get_users(
+    db: &State<DatabaseConnection>,
+    _user: Manager,
+) -> Result<Json<Vec<user::Model>>, ApiError> {
+    let users = dao::user::get_users(db).await?;
+
+    Ok(Json(users))
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct NewUser {
+    username: String,
+    /// At least 8 characters.
+    password: String,
+    role: UserRole,
+}
+
+/// Creates a user and returns its id.
+#[openapi(tag = "auth")]
+#[post("/users", data = "<new_user>")]
+pub async fn create_user(
+    db: &State<DatabaseConnection>,
+    _user: Manager,
+    new_user: Json<NewUser>,
+) -> Result<Json<i32>, ApiError> {
+    let NewUser {
+        username,
+        password,
+        role,
+    } = new_user.0;
+    let id = insert_user(db, username, password, role).await?;
+
+    Ok(Json(id))
+}
+
+/// Validates and hashes the password, then creates the user.
+pub async fn insert_user(
+    db: &DatabaseConnection,
+    username: String,
+    password: String,
+    role: UserRole,
+) -> Result<i32, ApiError> {
+    let username = username.trim().to_string();
+    if username.is_empty() {
+        return Err(ApiError::BadRequest(String::from(
+            "The username is required.",
+        )));
+    }
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(ApiError::BadRequest(format!(
+            "The password must have at least {} characters.",
+            MIN_PASSWORD_LENGTH
+        )));
+    }
+
+    let password_hash = spawn_blocking(move || hash_password(&password))
+        .await
+        .map_err(|_| {
+            ApiError::Internal(String::from("Error occurs while hashing the password."))
+        })??;
+
+    Ok(dao::user::insert_user(db, username, password_hash, role).await?)
+}
@@ -1,343 +1,474 @@
-use crate::models::batch;
-use crate::{dao, models::item};
-extern crate umya_spreadsheet;
-use chrono;
-use rocket::{
-    form::Form,
-    fs::TempFile,
-    get, http, patch, post,
-    response::status::Custom,
-    serde::json::{json, Json, Value},
-    FromForm, State,
+use super::auth::{Clerk, Manager, Viewer};
+use super::error::ApiError;
+use super::etag::{IfMatch, Tagged};
+use super::params::{date_range, optional_date_range, pagination, sorting, Page, QueryDate};
+use super::spreadsheet::export::{export, Download, ExportFormat, Table, REPORT_HEADERS};
+use super::spreadsheet::{
+    date, map_columns, match_item, read_sheet, Field, ImportConfig, ImportReport, RowError, Upload,
 };
+use crate::dao::{self, audit::AuditContext};
+use crate::models::batch;
+use rocket::{form::Form, get, patch, post, serde::json::Json, serde::Serialize, FromForm, State};
 use rocket_okapi::openapi;
-use sea_orm::{DatabaseConnection, TransactionTrait};
-use std::{env::temp_dir, ops::Add};
-use uuid::Uuid;
+use schemars::JsonSchema;
+use sea_orm::{DatabaseConnection, Order, TransactionTrait};
 
 #[openapi(tag = "batch")]
 #[get("/stock-in-and-items?<from>&<to>")]
 pub async fn get_stock_in_and_items(
     db: &State<DatabaseConnection>,
-    from: String,
-    to: String,
-) -> Result<Json<Vec<dao::batch::StockInAndItem>>, Custom<Value>> {
-    let result = dao::batch::get_stock_in_and_items(db, from, to).await;
+    _user: Viewer,
+    from: QueryDate,
+    to: QueryDate,
+) -> Result<Json<Vec<dao::batch::StockInAndItem>>, ApiError> {
+    let (from, to) = date_range(from, to)?;
+    let stock_in_and_items = dao::batch::get_stock_in_and_items(db, from, to).await?;
+
+    Ok(Json(stock_in_and_items))
+}
 
-    match result {
-        Ok(stock_in_and_items) => Ok(Json(stock_in_and_items)),
-        Err(_) => Err(Custom(
-            http::Status::InternalServerError,
-            json!({
-              "error": {
-                "code": 500,
-                "reason": "Internal Server Error",
-                "description": "Error occurs while getting batches from the database."
-              }
-            }),
-        )),
+#[derive(FromForm, JsonSchema)]
+pub struct BatchQuery {
+    item_id: Option<i32>,
+    vendor: Option<String>,
+    /// Received on or after this date (YYYY-MM-DD).
+    received_from: Option<QueryDate>,
+    /// Received on or before this date (YYYY-MM-DD).
+    received_to: Option<QueryDate>,
+    /// Expiring on or after this date (YYYY-MM-DD).
+    expires_from: Option<QueryDate>,
+    /// Expiring on or before this date (YYYY-MM-DD).
+    expires_to: Option<QueryDate>,
+    /// Whether disabled batches are listed, `true` by default.
+    include_disabled: Option<bool>,
+    /// Case-insensitive search over the item name.
+    q: Option<String>,
+    /// Batch column to sort by; newest received first by default.
+    sort: Option<String>,
+    /// `asc` or `desc`.
+    order: Option<String>,
+    /// 1-based page number.
+    page: Option<usize>,
+    per_page: Option<usize>,
+}
+
+impl BatchQuery {
+    fn into_filter(self) -> Result<dao::batch::BatchFilter, ApiError> {
+        let (sort, order) = sorting(
+            self.sort.as_deref(),
+            self.order.as_deref(),
+            (batch::Column::Date, Order::Desc),
+        )?;
+
+        Ok(dao::batch::BatchFilter {
+            item_id: self.item_id,
+            vendor: self.vendor,
+            received: optional_date_range(
+                ("received_from", self.received_from),
+                ("received_to", self.received_to),
+            )?,
+            expires: optional_date_range(
+                ("expires_from", self.expires_from),
+                ("expires_to", self.expires_to),
+            )?,
+            include_disabled: self.include_disabled.unwrap_or(true),
+            search: self.q,
+            sort,
+            order,
+        })
     }
 }
 
 #[openapi(tag = "batch")]
-#[get("/batches-and-items")]
+#[get("/batches-and-items?<query..>")]
 pub async fn get_batches_and_items(
     db: &State<DatabaseConnection>,
-) -> Result<Json<Vec<dao::batch::BatchAndItem>>, Custom<Value>> {
-    let result = dao::batch::get_batches_and_items(db).await;
+    _user: Viewer,
+    query: BatchQuery,
+) -> Result<Json<Page<dao::batch::BatchAndItem>>, ApiError> {
+    let (page, per_page) = pagination(query.page, query.per_page)?;
+    let filter = query.into_filter()?;
+    let (items, total) = dao::batch::get_batches_and_items(db, filter, page, per_page).await?;
 
-    match result {
-        Ok(batches_and_items) => Ok(Json(batches_and_items)),
-        Err(_) => Err(Custom(
-            http::Status::InternalServerError,
-            json!({
-              "error": {
-                "code": 500,
-                "reason": "Internal Server Error",
-                "description": "Error occurs while getting batches from the database."
-              }
-            }),
-        )),
-    }
+    Ok(Json(Page {
+        items,
+        total,
+        page,
+        per_page,
+    }))
 }
 
-#[derive(FromForm)]
-pub struct Upload<'r> {
-    file: TempFile<'r>,
+/// Downloads the matching batches as an xlsx (default) or csv `format`
+/// sheet, which the batch import reads back; `page` and `per_page` are
+/// ignored.
+#[openapi(tag = "batch")]
+#[get("/batches-and-items/export?<format>&<query..>")]
+pub async fn export_batches_and_items(
+    db: &State<DatabaseConnection>,
+    _user: Viewer,
+    format: Option<String>,
+    query: BatchQuery,
+) -> Result<Download, ApiError> {
+    let format = ExportFormat::parse(format.as_deref())?;
+    let batches = dao::batch::get_all_batches_and_items(db, query.into_filter()?).await?;
+
+    export(
+        Table {
+            name: String::from("batches"),
+            headers: &[
+                "id",
+                "date",
+                "name",
+                "specification",
+                "unit",
+                "manufacturer",
+                "number",
+                "price",
+                "expiration",
+                "vendor",
+                "remaining",
+                "disabled",
+                "item_id",
+                "sku",
+            ],
+            rows: batches
+                .into_iter()
+                .map(|batch| {
+                    vec![
+                        batch.id.into(),
+                        batch.date.into(),
+                        batch.name.into(),
+                        batch.specification.into(),
+                        batch.unit.into(),
+                        batch.manufacturer.into(),
+                        batch.number.into(),
+                        batch.price.into(),
+                        batch.expiration.into(),
+                        batch.vendor.into(),
+                        batch.remaining.into(),
+                        batch.disabled.into(),
+                        batch.item_id.into(),
+                        batch.sku.into(),
+                    ]
+                })
+                .collect(),
+        },
+        format,
+    )
 }
 
-#[post("/batches-from-xlsx", data = "<upload>")]
-pub async fn create_batch_from_xlsx(
+/// Downloads the stock-in report for a period as an xlsx (default) or csv
+/// `format` sheet.
+#[openapi(tag = "batch")]
+#[get("/stock-in-and-items/export?<from>&<to>&<format>")]
+pub async fn export_stock_in_and_items(
     db: &State<DatabaseConnection>,
-    mut upload: Form<Upload<'_>>,
-) -> Result<(), Custom<Value>> {
-    let mut tmp_dir = temp_dir();
-    tmp_dir.push(format!("{}.xlsx", Uuid::new_v4()));
+    _user: Viewer,
+    from: QueryDate,
+    to: QueryDate,
+    format: Option<String>,
+) -> Result<Download, ApiError> {
+    let (from, to) = date_range(from, to)?;
+    let format = ExportFormat::parse(format.as_deref())?;
+    let stock_in_and_items = dao::batch::get_stock_in_and_items(db, from, to).await?;
 
-    let file = match tmp_dir.to_str() {
-        Some(file) => file.to_string(),
-        None => {
-            return Err(Custom(
-                http::Status::InternalServerError,
-                json!({
-                  "error": {
-                    "code": 500,
-                    "reason": "Internal Server Error",
-                    "description": "Error occurs while saving the xlsx file."
-                  }
-                }),
-            ))
-        }
-    };
-    match upload.file.persist_to(&file).await {
-        Ok(_) => (),
-        Err(_) => {
-            return Err(Custom(
-                http::Status::InternalServerError,
-                json!({
-                  "error": {
-                    "code": 500,
-                    "reason": "Internal Server Error",
-                    "description": "Error occurs while saving the xlsx file."
-                  }
-                }),
-            ))
-        }
-    };
+    export(
+        Table {
+            name: format!("stock-in-{}-{}", from, to),
+            headers: REPORT_HEADERS,
+            rows: stock_in_and_items
+                .into_iter()
+                .map(|stock_in| {
+                    vec![
+                        stock_in.item_id.into(),
+                        stock_in.name.into(),
+                        stock_in.specification.into(),
+                        stock_in.unit.into(),
+                        stock_in.manufacturer.into(),
+                        stock_in.price.into(),
+                        stock_in.number.into(),
+                    ]
+                })
+                .collect(),
+        },
+        format,
+    )
+}
 
-    let path = std::path::Path::new(&file);
-    let book = match umya_spreadsheet::reader::xlsx::read(path) {
-        Ok(result) => result,
-        Err(_) => {
-            match rocket::tokio::fs::remove_file(path).await {
-                Ok(_) => (),
-                Err(_) => {
-                    return Err(Custom(
-                        http::Status::InternalServerError,
-                        json!({
-                          "error": {
-                            "code": 500,
-                            "reason": "Internal Server Error",
-                            "description": "Error occurs while deleting the xlsx file."
-                          }
-                        }),
-                    ))
-                }
-            };
-            return Err(Custom(
-                http::Status::InternalServerError,
-                json!({
-                  "error": {
-                    "code": 500,
-                    "reason": "Internal Server Error",
-                    "description": "Error occurs while selecting workbook from the xlsx file."
-                  }
-                }),
-            ));
-        }
-    };
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BatchImportRow {
+    row: u32,
+    name: String,
+    manufacturer: String,
+    /// The matched item, or the one created for this row.
+    item_id: i32,
+    /// Whether the row creates a new item instead of matching one.
+    new_item: bool,
+}
 
-    async fn delete_and_error(path: &std::path::Path) -> Result<(), Custom<Value>> {
-        match rocket::tokio::fs::remove_file(path).await {
-            Ok(_) => (),
-            Err(_) => {
-                return Err(Custom(
-                    http::Status::InternalServerError,
-                    json!({
-                      "error": {
-                        "code": 500,
-                        "reason": "Internal Server Error",
-                        "description": "Error occurs while deleting the xlsx file."
-                      }
-                    }),
-                ))
-            }
-        };
-        return Err(Custom(
-            http::Status::InternalServerError,
-            json!({
-              "error": {
-                "code": 500,
-                "reason": "Internal Server Error",
-                "description": "Error occurs while selecting sheet from the xlsx file."
-              }
-            }),
-        ));
-    }
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BatchImport {
+    #[serde(flatten)]
+    report: ImportReport<BatchImportRow>,
+    /// Batches and items the import creates, or would create once every row
+    /// is valid.
+    batches_created: usize,
+    items_created: usize,
+}
 
-    let sheet = match book.get_sheet(&0) {
-        Ok(result) => result,
-        Err(_) => return delete_and_error(&path).await,
-    };
+const BATCH_FIELDS: &[Field] = &[
+    Field {
+        name: "date",
+        aliases: &["received", "日期", "入库日期"],
+        required: true,
+    },
+    Field {
+        name: "name",
+        aliases: &["item", "名称", "品名"],
+        required: true,
+    },
+    Field {
+        name: "specification",
+        aliases: &["spec", "规格"],
+        required: false,
+    },
+    Field {
+        name: "unit",
+        aliases: &["单位"],
+        required: false,
+    },
+    Field {
+        name: "manufacturer",
+        aliases: &["厂家", "生产厂家", "生产企业"],
+        required: true,
+    },
+    Field {
+        name: "number",
+        aliases: &["quantity", "qty", "数量"],
+        required: true,
+    },
+    Field {
+        name: "price",
+        aliases: &["单价", "价格"],
+        required: true,
+    },
+    Field {
+        name: "expiration",
+        aliases: &["expiry", "expires", "有效期", "有效期至", "失效日期"],
+        required: true,
+    },
+    Field {
+        name: "vendor",
+        aliases: &["supplier", "供应商", "供货单位"],
+        required: false,
+    },
+    Field {
+        name: "sku",
+        aliases: &["barcode", "条码", "条形码", "货号"],
+        required: false,
+    },
+];
 
-    let transaction = match db.begin().await {
-        Ok(transaction) => transaction,
-        Err(_) => return delete_and_error(&path).await,
-    };
-    let items = match dao::item::get_items(&transaction).await {
-        Ok(items) => items,
-        Err(_) => return delete_and_error(&path).await,
-    };
+/// Imports batches from the first sheet of an xlsx, ods or csv file, one per
+/// row below the header row; blank rows are skipped. Columns are found by
+/// their headers (see [`BATCH_FIELDS`]), or by the given mapping `profile`.
+/// Items are matched by [`match_item`] and created when none matches.
+///
+/// With `dry_run` the import is only previewed and nothing is recorded.
+#[post("/batches-from-xlsx?<dry_run>&<profile>", data = "<upload>")]
+pub async fn create_batch_from_xlsx(
+    db: &State<DatabaseConnection>,
+    _user: Clerk,
+    context: AuditContext,
+    config: &State<ImportConfig>,
+    upload: Form<Upload>,
+    dry_run: Option<bool>,
+    profile: Option<String>,
+) -> Result<Json<BatchImport>, ApiError> {
+    let dry_run = dry_run.unwrap_or(false);
+    let sheet = &read_sheet(&upload.file)?;
+    let columns = map_columns(sheet, BATCH_FIELDS, config, profile.as_deref())?;
+    let cell = |field: &str, row: u32| columns.value(sheet, field, row);
 
-    let get_cell_value_string = |col, row| {
-        sheet
-            .get_cell_value_by_column_and_row(&col, &row)
-            .get_value()
-            .to_string()
-    };
+    let transaction = db.begin().await?;
+    let mut items = dao::item::get_items(&transaction).await?;
 
-    for i in 2..=sheet.get_highest_row() {
-        let date = chrono::NaiveDate::from_ymd(1900, 1, 1).add(chrono::Duration::days(
-            match get_cell_value_string(1, i).parse::<i64>() {
-                Ok(val) => val,
-                Err(_) => return delete_and_error(&path).await,
-            } - 2,
-        ));
-        let name = get_cell_value_string(2, i);
-        let specification = get_cell_value_string(3, i);
-        let unit = get_cell_value_string(4, i);
-        let manufacturer = get_cell_value_string(5, i);
-        let number = match get_cell_value_string(6, i).parse::<i32>() {
-            Ok(number) => number,
-            Err(_) => return delete_and_error(&path).await,
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for row in 2..=sheet.highest_row() {
+        if sheet.is_blank_row(row) {
+            continue;
+        }
+        let errors_before = errors.len();
+        let mut cell_error = |column: &str, message: String| {
+            errors.push(RowError {
+                row,
+                column: Some(column.to_string()),
+                message,
+            })
         };
-        let price = match get_cell_value_string(7, i).parse::<f32>() {
-            Ok(val) => val,
-            Err(_) => return delete_and_error(&path).await,
+
+        let mut date_cell = |column: &str| {
+            let value = cell(column, row);
+            let date = date::parse(&value, sheet.date1904);
+            if date.is_none() {
+                cell_error(column, format!("'{}' is not a date.", value));
+            }
+            date
         };
-        let expiration = chrono::NaiveDate::from_ymd(1900, 1, 1).add(chrono::Duration::days(
-            match get_cell_value_string(8, i).parse::<i64>() {
-                Ok(val) => val,
-                Err(_) => return delete_and_error(&path).await,
-            } - 2,
-        ));
-        let vendor = get_cell_value_string(9, i);
+        let received = date_cell("date");
+        let expiration = date_cell("expiration");
 
-        let item_matched: Vec<&item::Model> = items
-            .iter()
-            .filter(|item| item.name == name && item.manufacturer == manufacturer)
-            .collect();
+        let name = cell("name", row);
+        if name.is_empty() {
+            cell_error("name", String::from("The name is required."));
+        }
+        let specification = cell("specification", row);
+        let unit = cell("unit", row);
+        let manufacturer = cell("manufacturer", row);
+        if manufacturer.is_empty() {
+            cell_error(
+                "manufacturer",
+                String::from("The manufacturer is required."),
+            );
+        }
+        let number = cell("number", row);
+        let number = match number.parse::<i32>() {
+            Ok(number) if number > 0 => Some(number),
+            _ => {
+                cell_error(
+                    "number",
+                    format!("'{}' is not a positive whole number.", number),
+                );
+                None
+            }
+        };
+        let price = cell("price", row);
+        let price = match price.parse::<f32>() {
+            Ok(price) if price.is_finite() => Some(price),
+            _ => {
+                cell_error("price", format!("'{}' is not a price.", price));
+                None
+            }
+        };
+        let vendor = cell("vendor", row);
 
-        if item_matched.len() == 1 {
-            match dao::batch::create_batch_transaction(
-                &transaction,
-                batch::Model {
-                    id: 0,
-                    date,
-                    number,
-                    expiration,
-                    vendor: Some(vendor),
-                    disabled: 0,
-                    item_id: item_matched[0].id,
-                },
-            )
-            .await
-            {
-                Ok(_) => (),
-                Err(_) => return delete_and_error(&path).await,
-            };
-        } else {
-            let item_id = match dao::item::insert_item_transaction(
-                &transaction,
-                item::Model {
-                    id: 0,
-                    name,
-                    specification: Some(specification),
-                    unit: Some(unit),
-                    manufacturer,
-                    number: 0,
-                    price,
-                    expiration: chrono::NaiveDate::from_ymd(2099, 12, 31),
-                },
-            )
-            .await
+        let (received, expiration, number, price) = match (received, expiration, number, price) {
+            (Some(received), Some(expiration), Some(number), Some(price))
+                if errors.len() == errors_before =>
             {
-                Ok(val) => val,
-                Err(_) => return delete_and_error(&path).await,
+                (received, expiration, number, price)
             }
-            .last_insert_id;
+            _ => continue,
+        };
 
-            match dao::batch::create_batch_transaction(
-                &transaction,
-                batch::Model {
-                    id: 0,
-                    date,
-                    number,
-                    expiration,
-                    vendor: Some(vendor),
-                    disabled: 0,
-                    item_id: item_id,
-                },
-            )
-            .await
-            {
-                Ok(_) => (),
-                Err(_) => return delete_and_error(&path).await,
+        let sku = cell("sku", row);
+
+        let item_matched = match match_item(&items, &sku, &name, &manufacturer, &specification) {
+            Ok(item) => item,
+            Err(message) => {
+                errors.push(RowError {
+                    row,
+                    column: None,
+                    message,
+                });
+                continue;
+            }
+        };
+        let (item_id, new_item) = if let Some(item) = item_matched {
+            (item.id, false)
+        } else {
+            let details = dao::item::ItemDetails {
+                name: name.clone(),
+                specification: Some(specification),
+                unit: Some(unit),
+                manufacturer: manufacturer.clone(),
+                price,
+                sku: Some(sku),
             };
-        }
-    }
+            let item = dao::item::insert_item_transaction(&transaction, details, &context).await?;
+            let item_id = item.id;
+            // Later rows for the same item match the one created here.
+            items.push(item);
+            (item_id, true)
+        };
 
-    match transaction.commit().await {
-        Ok(_) => (),
-        Err(_) => return delete_and_error(&path).await,
+        dao::batch::create_batch_transaction(
+            &transaction,
+            batch::Model {
+                id: 0,
+                date: received,
+                number,
+                expiration,
+                vendor: Some(vendor),
+                disabled: false,
+                item_id,
+                remaining: number,
+                version: 1,
+            },
+            &context,
+        )
+        .await?;
+        rows.push(BatchImportRow {
+            row,
+            name,
+            manufacturer,
+            item_id,
+            new_item,
+        });
     }
 
-    match rocket::tokio::fs::remove_file(path).await {
-        Ok(_) => Ok(()),
-        Err(_) => {
-            return Err(Custom(
-                http::Status::InternalServerError,
-                json!({
-                  "error": {
-                    "code": 500,
-                    "reason": "Internal Server Error",
-                    "description": "Error occurs while deleting the xlsx file."
-                  }
-                }),
-            ))
-        }
-    }
+    let batches_created = rows.len();
+    let items_created = rows.iter().filter(|row| row.new_item).count();
+    let report = ImportReport::finish(transaction, dry_run, rows, errors).await?;
+
+    Ok(Json(BatchImport {
+        report,
+        batches_created,
+        items_created,
+    }))
+}
+
+/// Gets a batch, with its version as the `ETag` to send back as `If-Match`
+/// when changing it.
+#[openapi(tag = "batch")]
+#[get("/batches/<id>")]
+pub async fn get_batch(
+    db: &State<DatabaseConnection>,
+    _user: Viewer,
+    id: i32,
+) -> Result<Tagged<Json<batch::Model>>, ApiError> {
+    let batch = dao::batch::get_batch(db, id).await?;
+
+    Ok(Tagged(batch.version, Json(batch)))
 }
 
 #[openapi(tag = "batch")]
 #[post("/batches", data = "<batch>")]
 pub async fn create_batch(
     db: &State<DatabaseConnection>,
+    _user: Clerk,
+    context: AuditContext,
     batch: Json<batch::Model>,
-) -> Result<(), Custom<Value>> {
-    let result = dao::batch::create_batch(db, batch.0).await;
+) -> Result<(), ApiError> {
+    dao::batch::create_batch(db, batch.0, &context).await?;
 
-    match result {
-        Ok(_) => Ok(()),
-        Err(_) => Err(Custom(
-            http::Status::InternalServerError,
-            json!({
-              "error": {
-                "code": 500,
-                "reason": "Internal Server Error",
-                "description": "Error occurs while getting batches from the database."
-              }
-            }),
-        )),
-    }
+    Ok(())
 }
 
 #[openapi(tag = "batch")]
-#[patch("/batches/<id>")]
-pub async fn disable_batch(db: &State<DatabaseConnection>, id: u32) -> Result<(), Custom<Value>> {
-    let result = dao::batch::disable_batch(db, id).await;
+#[patch("/batches/<id>", data = "<patch>")]
+pub async fn modify_batch(
+    db: &State<DatabaseConnection>,
+    _user: Manager,
+    context: AuditContext,
+    if_match: IfMatch,
+    id: i32,
+    patch: Json<dao::batch::BatchPatch>,
+) -> Result<Tagged<Json<batch::Model>>, ApiError> {
+    let batch = dao::batch::modify_batch(db, id, patch.0, if_match.version()?, &context).await?;
 
-    match result {
-        Ok(_) => Ok(()),
-        Err(_) => Err(Custom(
-            http::Status::InternalServerError,
-            json!({
-              "error": {
-                "code": 500,
-                "reason": "Internal Server Error",
-                "description": "Error occurs while disabling batch from the database."
-              }
-            }),
-        )),
-    }
+    Ok(Tagged(batch.version, Json(batch)))
 }
@@ -0,0 +1,337 @@
+use crate::dao::{
+    batch::BatchError,
+    db::{StockOverflow, VersionConflict},
+    item::ItemError,
+    stock_adjustment::StockAdjustmentError,
+    stock_out::StockOutError,
+};
+use rocket::{
+    http::Status,
+    response::{self, status::Custom, Responder},
+    serde::json::Json,
+    Request,
+};
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    okapi::openapi3::{MediaType, RefOr, Response, Responses},
+    response::OpenApiResponderInner,
+};
+use sea_orm::DbErr;
+
+/// Error returned by every API handler, rendered as an [`ErrorResponse`].
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    InsufficientStock {
+        available: i32,
+        requested: i32,
+    },
+    PreconditionFailed(String),
+    PayloadTooLarge(String),
+    UnprocessableEntity(String),
+    ServiceUnavailable(String),
+    Internal(String),
+    /// Any other error status, as caught from Rocket.
+    Other(Status, String),
+}
+
+#[derive(rocket_okapi::JsonSchema, rocket::serde::Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ErrorResponse {
+    pub error: ErrorDetail,
+}
+
+#[derive(rocket_okapi::JsonSchema, rocket::serde::Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ErrorDetail {
+    pub code: u16,
+    pub reason: String,
+    pub description: String,
+    /// Stock still available, set when a stock-out is refused.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub available: Option<i32>,
+}
+
+impl ApiError {
+    pub fn status(&self) -> Status {
+        match self {
+            ApiError::BadRequest(_) => Status::BadRequest,
+            ApiError::Unauthorized(_) => Status::Unauthorized,
+            ApiError::Forbidden(_) => Status::Forbidden,
+            ApiError::NotFound(_) => Status::NotFound,
+            ApiError::Conflict(_) | ApiError::InsufficientStock { .. } => Status::Conflict,
+            ApiError::PreconditionFailed(_) => Status::PreconditionFailed,
+            ApiError::PayloadTooLarge(_) => Status::PayloadTooLarge,
+            ApiError::UnprocessableEntity(_) => Status::UnprocessableEntity,
+            ApiError::ServiceUnavailable(_) => Status::ServiceUnavailable,
+            ApiError::Internal(_) => Status::InternalServerError,
+            ApiError::Other(status, _) => *status,
+        }
+    }
+
+    pub fn body(&self) -> ErrorResponse {
+        let status = self.status();
+        let description = match self {
+            ApiError::BadRequest(description)
+            | ApiError::Unauthorized(description)
+            | ApiError::Forbidden(description)
+            | ApiError::NotFound(description)
+            | ApiError::Conflict(description)
+            | ApiError::PreconditionFailed(description)
+            | ApiError::PayloadTooLarge(description)
+            | ApiError::UnprocessableEntity(description)
+            | ApiError::ServiceUnavailable(description)
+            | ApiError::Internal(description)
+            | ApiError::Other(_, description) => description.clone(),
+            ApiError::InsufficientStock {
+                available,
+                requested,
+            } => format!(
+                "Insufficient stock: {} requested but only {} available.",
+                requested, available
+            ),
+        };
+        let available = match self {
+            ApiError::InsufficientStock { available, .. } => Some(*available),
+            _ => None,
+        };
+
+        ErrorResponse {
+            error: ErrorDetail {
+                code: status.code,
+                reason: status.reason_lossy().to_string(),
+                description,
+                available,
+            },
+        }
+    }
+}
+
+/// The error for a status Rocket answered itself, e.g. for a body that does
+/// not parse or a handler that panicked.
+impl From<Status> for ApiError {
+    fn from(status: Status) -> Self {
+        match status.code {
+            400 => ApiError::BadRequest(String::from(
+                "The request is malformed; check its query, form fields and body.",
+            )),
+            422 => ApiError::UnprocessableEntity(String::from(
+                "The request body does not have the expected fields and types.",
+            )),
+            500 => ApiError::Internal(String::from("Error occurs while handling the request.")),
+            _ => ApiError::Other(status, status.reason_lossy().to_string()),
+        }
+    }
+}
+
+/// Whether a database error message reports a violated unique or foreign key
+/// constraint. The drivers only expose it in the message text, which differs
+/// between MySQL, PostgreSQL and SQLite.
+fn is_constraint_violation(message: &str) -> bool {
+    let message = message.to_lowercase();
+    [
+        "duplicate entry",
+        "foreign key constraint",
+        "unique constraint",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
+
+impl From<DbErr> for ApiError {
+    fn from(err: DbErr) -> Self {
+        match err {
+            DbErr::RecordNotFound(description) => ApiError::NotFound(description),
+            DbErr::Exec(message) | DbErr::Query(message) if is_constraint_violation(&message) => {
+                ApiError::Conflict(format!(
+                    "Request conflicts with existing records: {}",
+                    message
+                ))
+            }
+            DbErr::Conn(message) => {
+                eprintln!("Database connection error: {}", message);
+                ApiError::ServiceUnavailable(String::from("The database is unavailable."))
+            }
+            err => {
+                eprintln!("Database error: {}", err);
+                ApiError::Internal(String::from("Error occurs while accessing the database."))
+            }
+        }
+    }
+}
+
+impl From<VersionConflict> for ApiError {
+    fn from(conflict: VersionConflict) -> Self {
+        match conflict {
+            VersionConflict::Precondition {
+                entity,
+                id,
+                current,
+            } => ApiError::PreconditionFailed(format!(
+                "{} {} has changed and is now at version {}; reload it and try again.",
+                entity, id, current
+            )),
+            VersionConflict::Concurrent { entity, id } => ApiError::Conflict(format!(
+                "{} {} was changed by another request at the same time; try again.",
+                entity, id
+            )),
+        }
+    }
+}
+
+impl From<StockOverflow> for ApiError {
+    fn from(overflow: StockOverflow) -> Self {
+        ApiError::Conflict(format!(
+            "The stock of item {} would go beyond the {} units that can be recorded.",
+            overflow.item_id,
+            i32::MAX
+        ))
+    }
+}
+
+impl From<StockOutError> for ApiError {
+    fn from(err: StockOutError) -> Self {
+        match err {
+            StockOutError::Db(err) => err.into(),
+            StockOutError::InvalidNumber(number) => ApiError::BadRequest(format!(
+                "Stock-out number must be positive, got {}.",
+                number
+            )),
+            StockOutError::InsufficientStock {
+                available,
+                requested,
+            } => ApiError::InsufficientStock {
+                available,
+                requested,
+            },
+            StockOutError::AlreadyReversed(id) => {
+                ApiError::Conflict(format!("Stock-out {} was already reversed.", id))
+            }
+            StockOutError::Version(conflict) => conflict.into(),
+            StockOutError::Overflow(overflow) => overflow.into(),
+        }
+    }
+}
+
+impl From<BatchError> for ApiError {
+    fn from(err: BatchError) -> Self {
+        match err {
+            BatchError::Db(err) => err.into(),
+            BatchError::InvalidNumber(number) => {
+                ApiError::BadRequest(format!("Batch number must be positive, got {}.", number))
+            }
+            BatchError::BelowDrawn { drawn, requested } => ApiError::Conflict(format!(
+                "Batch number {} is less than the {} already drawn by stock-outs.",
+                requested, drawn
+            )),
+            BatchError::MissingReason => ApiError::BadRequest(String::from(
+                "Disabling a batch requires a reason: expired, recalled, damaged or lost.",
+            )),
+            BatchError::Version(conflict) => conflict.into(),
+            BatchError::Overflow(overflow) => overflow.into(),
+        }
+    }
+}
+
+impl From<StockAdjustmentError> for ApiError {
+    fn from(err: StockAdjustmentError) -> Self {
+        match err {
+            StockAdjustmentError::Db(err) => err.into(),
+            StockAdjustmentError::InsufficientStock {
+                available,
+                requested,
+            } => ApiError::InsufficientStock {
+                available,
+                requested,
+            },
+            StockAdjustmentError::MissingBatch => ApiError::BadRequest(String::from(
+                "Adding stock requires the batch_id of the batch it belongs to.",
+            )),
+            StockAdjustmentError::InvalidBatch { batch_id, item_id } => {
+                ApiError::BadRequest(format!(
+                    "Batch {} is not an enabled batch of item {}.",
+                    batch_id, item_id
+                ))
+            }
+            StockAdjustmentError::Version(conflict) => conflict.into(),
+            StockAdjustmentError::Overflow(overflow) => overflow.into(),
+        }
+    }
+}
+
+impl From<ItemError> for ApiError {
+    fn from(err: ItemError) -> Self {
+        match err {
+            ItemError::Db(err) => err.into(),
+            ItemError::HasHistory {
+                id,
+                batches,
+                stock_outs,
+                adjustments,
+            } => ApiError::Conflict(format!(
+                "Item {} has {} batches, {} stock-outs and {} stock adjustments; \
+                archive it instead, or purge them with it.",
+                id, batches, stock_outs, adjustments
+            )),
+            ItemError::Version(conflict) => conflict.into(),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        Custom(self.status(), Json(self.body())).respond_to(request)
+    }
+}
+
+/// Documents error responses, each with an [`ErrorResponse`] body.
+pub fn error_responses(gen: &mut OpenApiGenerator, statuses: &[(u16, &str)]) -> Responses {
+    let schema = gen.json_schema::<ErrorResponse>();
+    let mut responses = Responses::default();
+
+    for (status, description) in statuses {
+        let mut content = rocket_okapi::okapi::Map::new();
+        content.insert(
+            "application/json".to_owned(),
+            MediaType {
+                schema: Some(schema.clone()),
+                ..MediaType::default()
+            },
+        );
+        responses.responses.insert(
+            status.to_string(),
+            RefOr::Object(Response {
+                description: description.to_string(),
+                content,
+                ..Response::default()
+            }),
+        );
+    }
+
+    responses
+}
+
+impl OpenApiResponderInner for ApiError {
+    fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        Ok(error_responses(
+            gen,
+            &[
+                (400, "The request is malformed or fails validation."),
+                (404, "The requested record does not exist."),
+                (
+                    409,
+                    "The request conflicts with the current stock or records.",
+                ),
+                (412, "The record has changed since the `If-Match` version."),
+                (413, "The upload is larger than the configured maximum."),
+                (422, "The request body does not have the expected shape."),
+                (500, "An unexpected error occurred."),
+                (503, "The database is unavailable."),
+            ],
+        ))
+    }
+}
@@ -0,0 +1,103 @@
+//! Versions of items and batches as HTTP entity tags.
+//!
+//! A record's `ETag` is its version in quotes, e.g. `"3"`. Changes sent with
+//! `If-Match` are refused with 412 once the record has moved on.
+
+use super::error::ApiError;
+use rocket::{
+    async_trait,
+    http::Header,
+    request::{FromRequest, Outcome},
+    response::{self, Responder},
+    Request,
+};
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    okapi::openapi3::{Object, Parameter, ParameterValue, Responses},
+    request::{OpenApiFromRequest, RequestHeaderInput},
+    response::OpenApiResponderInner,
+};
+
+/// The version named by the `If-Match` header. Without the header, or with
+/// `If-Match: *`, any version will do.
+///
+/// A malformed header, or a weak `W/` tag, is kept and turned into a 400 by
+/// [`IfMatch::version`].
+pub struct IfMatch(Result<Option<i32>, String>);
+
+impl IfMatch {
+    pub fn version(self) -> Result<Option<i32>, ApiError> {
+        self.0.map_err(ApiError::BadRequest)
+    }
+}
+
+fn parse_if_match(value: &str) -> Result<Option<i32>, String> {
+    let tag = value.trim();
+    if tag == "*" {
+        return Ok(None);
+    }
+    tag.strip_prefix('"')
+        .and_then(|tag| tag.strip_suffix('"'))
+        .and_then(|version| version.parse().ok())
+        .map(Some)
+        .ok_or_else(|| format!("Invalid If-Match '{}', expected an ETag like \"3\".", value))
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for IfMatch {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        let version = match request.headers().get_one("If-Match") {
+            Some(value) => parse_if_match(value),
+            None => Ok(None),
+        };
+        Outcome::Success(IfMatch(version))
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for IfMatch {
+    fn from_request_input(
+        gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::Parameter(Parameter {
+            name: String::from("If-Match"),
+            location: String::from("header"),
+            description: Some(String::from(
+                "The `ETag` the record was read with; the change is refused with 412 \
+                if the record has changed since.",
+            )),
+            required: false,
+            deprecated: false,
+            allow_empty_value: false,
+            value: ParameterValue::Schema {
+                style: None,
+                explode: None,
+                allow_reserved: false,
+                schema: gen.json_schema::<String>(),
+                example: None,
+                examples: None,
+            },
+            extensions: Object::default(),
+        }))
+    }
+}
+
+/// A response about a record, with the record's version as its `ETag`.
+pub struct Tagged<R>(pub i32, pub R);
+
+impl<'r, R: Responder<'r, 'static>> Responder<'r, 'static> for Tagged<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = self.1.respond_to(request)?;
+        response.set_header(Header::new("ETag", format!("\"{}\"", self.0)));
+        Ok(response)
+    }
+}
+
+impl<R: OpenApiResponderInner> OpenApiResponderInner for Tagged<R> {
+    fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        R::responses(gen)
+    }
+}
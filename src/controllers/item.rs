@@ -1,134 +1,306 @@
-use crate::dao;
-use crate::models::item;
+use super::auth::{Clerk, Manager, Viewer};
+use super::error::ApiError;
+use super::etag::{IfMatch, Tagged};
+use super::params::{pagination, sorting, Page, QueryDate};
+use super::spreadsheet::export::{export, Download, ExportFormat, Table};
+use crate::dao::{self, audit::AuditContext, stock_adjustment::StockChange};
+use crate::models::{item, stock_adjustment, stock_adjustment_batch};
 use rocket::{
-    delete, get, http, post, put,
-    response::status::Custom,
-    serde::json::{json, Json, Value},
-    State,
+    delete, get, post, put,
+    serde::{json::Json, Deserialize},
+    FromForm, State,
 };
 use rocket_okapi::openapi;
-use sea_orm::{DatabaseConnection, DbErr};
+use schemars::JsonSchema;
+use sea_orm::{DatabaseConnection, Order};
+
+#[derive(FromForm, JsonSchema)]
+pub struct ItemQuery {
+    /// Case-insensitive search over name, manufacturer, specification and SKU.
+    q: Option<String>,
+    /// Only items with at most this many in stock.
+    low_stock: Option<i32>,
+    /// Only items expiring before this date (YYYY-MM-DD).
+    expiring_before: Option<QueryDate>,
+    /// List the archived items instead, `false` by default.
+    archived: Option<bool>,
+    /// Column to sort by, `id` by default.
+    sort: Option<String>,
+    /// `asc` (default) or `desc`.
+    order: Option<String>,
+    /// 1-based page number.
+    page: Option<usize>,
+    per_page: Option<usize>,
+}
+
+impl ItemQuery {
+    fn into_filter(self) -> Result<dao::item::ItemFilter, ApiError> {
+        let (sort, order) = sorting(
+            self.sort.as_deref(),
+            self.order.as_deref(),
+            (item::Column::Id, Order::Asc),
+        )?;
+
+        Ok(dao::item::ItemFilter {
+            search: self.q,
+            low_stock: self.low_stock,
+            expiring_before: QueryDate::optional(self.expiring_before)?,
+            archived: self.archived.unwrap_or(false),
+            sort,
+            order,
+        })
+    }
+}
 
 #[openapi(tag = "item")]
-#[get("/items")]
+#[get("/items?<query..>")]
 pub async fn get_items(
     db: &State<DatabaseConnection>,
-) -> Result<Json<Vec<item::Model>>, Custom<Value>> {
-    let items = dao::item::get_items(db as &DatabaseConnection).await;
-
-    match items {
-        Ok(items) => Ok(Json(items)),
-        Err(_) => Err(Custom(
-            http::Status::InternalServerError,
-            json!({
-              "error": {
-                "code": 500,
-                "reason": "Internal Server Error",
-                "description": "Error occurs while getting items from the database."
-              }
-            }),
-        )),
-    }
+    _user: Viewer,
+    query: ItemQuery,
+) -> Result<Json<Page<item::Model>>, ApiError> {
+    let (page, per_page) = pagination(query.page, query.per_page)?;
+    let filter = query.into_filter()?;
+    let (items, total) = dao::item::search_items(db, filter, page, per_page).await?;
+
+    Ok(Json(Page {
+        items,
+        total,
+        page,
+        per_page,
+    }))
+}
+
+/// Downloads the matching items as an xlsx (default) or csv `format` sheet;
+/// `page` and `per_page` are ignored.
+#[openapi(tag = "item")]
+#[get("/items/export?<format>&<query..>")]
+pub async fn export_items(
+    db: &State<DatabaseConnection>,
+    _user: Viewer,
+    format: Option<String>,
+    query: ItemQuery,
+) -> Result<Download, ApiError> {
+    let format = ExportFormat::parse(format.as_deref())?;
+    let items = dao::item::search_all_items(db, query.into_filter()?).await?;
+
+    export(
+        Table {
+            name: String::from("items"),
+            headers: &[
+                "id",
+                "name",
+                "specification",
+                "unit",
+                "manufacturer",
+                "number",
+                "price",
+                "expiration",
+                "sku",
+            ],
+            rows: items
+                .into_iter()
+                .map(|item| {
+                    vec![
+                        item.id.into(),
+                        item.name.into(),
+                        item.specification.into(),
+                        item.unit.into(),
+                        item.manufacturer.into(),
+                        item.number.into(),
+                        item.price.into(),
+                        item.expiration.into(),
+                        item.sku.into(),
+                    ]
+                })
+                .collect(),
+        },
+        format,
+    )
 }
 
+/// Gets an item, with its version as the `ETag` to send back as `If-Match`
+/// when changing it.
 #[openapi(tag = "item")]
-#[post("/items", data = "<item>")]
+#[get("/items/<id>")]
+pub async fn get_item(
+    db: &State<DatabaseConnection>,
+    _user: Viewer,
+    id: i32,
+) -> Result<Tagged<Json<item::Model>>, ApiError> {
+    let item = dao::item::get_item(db, id).await?;
+
+    Ok(Tagged(item.version, Json(item)))
+}
+
+/// Creates an item and returns its id. It starts without stock; receive a
+/// batch of it to stock it.
+#[openapi(tag = "item")]
+#[post("/items", data = "<details>")]
 pub async fn create_item(
     db: &State<DatabaseConnection>,
-    item: Json<item::Model>,
-) -> Result<Json<u32>, Custom<Value>> {
-    let result = dao::item::insert_item(db as &DatabaseConnection, item.0).await;
-
-    match result {
-        Ok(res) => Ok(Json(res.last_insert_id)),
-        Err(_) => Err(Custom(
-            http::Status::InternalServerError,
-            json!({
-              "error": {
-                "code": 500,
-                "reason": "Internal Server Error",
-                "description": "Error occurs while inserting new item into the database."
-              }
-            }),
-        )),
-    }
+    _user: Clerk,
+    context: AuditContext,
+    details: Json<dao::item::ItemDetails>,
+) -> Result<Json<i32>, ApiError> {
+    let item = dao::item::insert_item(db, details.0, &context).await?;
+
+    Ok(Json(item.id))
 }
 
+/// Edits an item's descriptive fields. Its stock and expiration are not
+/// taken from the body; see [`adjust_stock`].
 #[openapi(tag = "item")]
-#[put("/items/<id>", data = "<item>")]
+#[put("/items/<id>", data = "<details>")]
 pub async fn modify_item(
     db: &State<DatabaseConnection>,
-    id: u32,
-    item: Json<item::Model>,
-) -> Result<(), Custom<Value>> {
-    if id != item.id {
-        return Err(Custom(
-            http::Status::Conflict,
-            json!({
-                "error": {
-                    "code": 409,
-                    "reason": "Conflict",
-                    "description": "Conflict parameters: id does not equal to item.id."
-                }
-            }),
-        ));
-    }
+    _user: Clerk,
+    context: AuditContext,
+    if_match: IfMatch,
+    id: i32,
+    details: Json<dao::item::ItemDetails>,
+) -> Result<Tagged<Json<item::Model>>, ApiError> {
+    let item = dao::item::modify_item(db, id, details.0, if_match.version()?, &context).await?;
 
-    match dao::item::modify_item(db as &DatabaseConnection, item.0).await {
-        Ok(_) => Ok(()),
-        Err(err) => match err {
-            DbErr::RecordNotFound(_) => Err(Custom(
-                http::Status::NotFound,
-                json!({
-                  "error": {
-                    "code": 404,
-                    "reason": "Item Not Found",
-                    "description": "Item not found in the database."
-                  }
-                }),
-            )),
-            _ => Err(Custom(
-                http::Status::InternalServerError,
-                json!({
-                  "error": {
-                    "code": 500,
-                    "reason": "Internal Server Error",
-                    "description": "Error occurs while modifying item."
-                  }
-                }),
-            )),
-        },
-    }
+    Ok(Tagged(item.version, Json(item)))
 }
 
+#[derive(Deserialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct NewStockAdjustment {
+    /// Units to add, or to take away when negative.
+    delta: Option<i32>,
+    /// The stock counted at a stocktake, given instead of `delta`.
+    counted: Option<i32>,
+    /// The batch to add to or take from. Required when adding stock; stock
+    /// is otherwise taken from the batches expiring first.
+    batch_id: Option<i32>,
+    reason: String,
+}
+
+/// Corrects an item's stock by a `delta`, or sets it to a stocktake's
+/// `counted` stock, and records the adjustment with its reason and the
+/// batches it changed.
 #[openapi(tag = "item")]
-#[delete("/items/<id>")]
-pub async fn delete_item(db: &State<DatabaseConnection>, id: u32) -> Result<(), Custom<Value>> {
-    let result = dao::item::delete_item(db as &DatabaseConnection, id).await;
-
-    match result {
-        Ok(_) => Ok(()),
-        Err(err) => match err {
-            DbErr::RecordNotFound(_) => Err(Custom(
-                http::Status::NotFound,
-                json!({
-                  "error": {
-                    "code": 404,
-                    "reason": "Item Not Found",
-                    "description": "Item not found in the database."
-                  }
-                }),
-            )),
-            _ => Err(Custom(
-                http::Status::InternalServerError,
-                json!({
-                  "error": {
-                    "code": 500,
-                    "reason": "Internal Server Error",
-                    "description": "Error occurs while deleting item."
-                  }
-                }),
-            )),
-        },
+#[post("/items/<id>/stock-adjustments", data = "<adjustment>")]
+pub async fn adjust_stock(
+    db: &State<DatabaseConnection>,
+    _user: Manager,
+    context: AuditContext,
+    if_match: IfMatch,
+    id: i32,
+    adjustment: Json<NewStockAdjustment>,
+) -> Result<Json<stock_adjustment::Model>, ApiError> {
+    let NewStockAdjustment {
+        delta,
+        counted,
+        batch_id,
+        reason,
+    } = adjustment.0;
+    let change = match (delta, counted) {
+        (Some(0), None) => {
+            return Err(ApiError::BadRequest(String::from(
+                "An adjustment must change the stock.",
+            )))
+        }
+        (Some(delta), None) => StockChange::Delta(delta),
+        (None, Some(counted)) if counted >= 0 => StockChange::Counted(counted),
+        (None, Some(counted)) => {
+            return Err(ApiError::BadRequest(format!(
+                "The counted stock cannot be negative, got {}.",
+                counted
+            )))
+        }
+        _ => {
+            return Err(ApiError::BadRequest(String::from(
+                "Give either delta or counted.",
+            )))
+        }
+    };
+    if reason.trim().is_empty() {
+        return Err(ApiError::BadRequest(String::from(
+            "A reason is required for a stock adjustment.",
+        )));
     }
+
+    let expected = if_match.version()?;
+    let adjustment =
+        dao::stock_adjustment::adjust_stock(db, id, change, batch_id, reason, expected, &context)
+            .await?;
+
+    Ok(Json(adjustment))
+}
+
+#[openapi(tag = "item")]
+#[get("/items/<id>/stock-adjustments")]
+pub async fn get_stock_adjustments(
+    db: &State<DatabaseConnection>,
+    _user: Viewer,
+    id: i32,
+) -> Result<Json<Vec<stock_adjustment::Model>>, ApiError> {
+    let adjustments = dao::stock_adjustment::get_stock_adjustments(db, id).await?;
+
+    Ok(Json(adjustments))
+}
+
+/// Lists how much a stock adjustment added to or took from each batch.
+#[openapi(tag = "item")]
+#[get("/stock-adjustments/<id>/batches")]
+pub async fn get_stock_adjustment_batches(
+    db: &State<DatabaseConnection>,
+    _user: Viewer,
+    id: i32,
+) -> Result<Json<Vec<stock_adjustment_batch::Model>>, ApiError> {
+    let batches = dao::stock_adjustment::get_stock_adjustment_batches(db, id).await?;
+
+    Ok(Json(batches))
+}
+
+/// Archives an item: it leaves the item list and imports, but its batches
+/// and stock-outs stay in the reports.
+#[openapi(tag = "item")]
+#[post("/items/<id>/archive")]
+pub async fn archive_item(
+    db: &State<DatabaseConnection>,
+    _user: Manager,
+    context: AuditContext,
+    if_match: IfMatch,
+    id: i32,
+) -> Result<Tagged<()>, ApiError> {
+    let item = dao::item::set_item_archived(db, id, true, if_match.version()?, &context).await?;
+
+    Ok(Tagged(item.version, ()))
+}
+
+/// Brings an archived item back.
+#[openapi(tag = "item")]
+#[post("/items/<id>/restore")]
+pub async fn restore_item(
+    db: &State<DatabaseConnection>,
+    _user: Manager,
+    context: AuditContext,
+    if_match: IfMatch,
+    id: i32,
+) -> Result<Tagged<()>, ApiError> {
+    let item = dao::item::set_item_archived(db, id, false, if_match.version()?, &context).await?;
+
+    Ok(Tagged(item.version, ()))
+}
+
+/// Deletes an item. Items with batches or stock-outs are refused with 409;
+/// archive them instead, or set `purge` to delete that history as well.
+#[openapi(tag = "item")]
+#[delete("/items/<id>?<purge>")]
+pub async fn delete_item(
+    db: &State<DatabaseConnection>,
+    _user: Manager,
+    context: AuditContext,
+    if_match: IfMatch,
+    id: i32,
+    purge: Option<bool>,
+) -> Result<(), ApiError> {
+    let expected = if_match.version()?;
+    dao::item::delete_item(db, id, purge.unwrap_or(false), expected, &context).await?;
+
+    Ok(())
 }
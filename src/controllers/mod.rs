@@ -1,3 +1,10 @@
+pub mod audit;
+pub mod auth;
 pub mod batch;
+pub mod error;
+pub mod etag;
 pub mod item;
+pub mod params;
+pub mod spreadsheet;
 pub mod stock_out;
+pub mod write_off;
@@ -0,0 +1,160 @@
+use super::error::ApiError;
+use rocket::form::{self, FromFormField, ValueField};
+use rocket::serde::Serialize;
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+
+pub const DEFAULT_PER_PAGE: usize = 50;
+pub const MAX_PER_PAGE: usize = 500;
+
+/// A `YYYY-MM-DD` query parameter.
+///
+/// Parse failures are kept instead of failing the query guard, which would
+/// make Rocket forward the request and answer 404. Handlers turn them into a
+/// 400 with [`date_range`].
+pub struct QueryDate(Result<chrono::NaiveDate, String>);
+
+impl<'v> FromFormField<'v> for QueryDate {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        let date = chrono::NaiveDate::parse_from_str(field.value, "%Y-%m-%d").map_err(|_| {
+            format!(
+                "Invalid '{}' date '{}', expected YYYY-MM-DD.",
+                field.name, field.value
+            )
+        });
+        Ok(QueryDate(date))
+    }
+
+    fn default() -> Option<Self> {
+        Some(QueryDate(Err(String::from("Missing date parameter."))))
+    }
+}
+
+impl QueryDate {
+    /// Validates an optional date; a missing parameter is `None`.
+    pub fn optional(date: Option<QueryDate>) -> Result<Option<chrono::NaiveDate>, ApiError> {
+        date.map(|date| date.0.map_err(ApiError::BadRequest))
+            .transpose()
+    }
+}
+
+impl JsonSchema for QueryDate {
+    fn schema_name() -> String {
+        chrono::NaiveDate::schema_name()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        chrono::NaiveDate::json_schema(gen)
+    }
+}
+
+/// Validates a `from`/`to` pair of query dates, both ends inclusive.
+pub fn date_range(
+    from: QueryDate,
+    to: QueryDate,
+) -> Result<(chrono::NaiveDate, chrono::NaiveDate), ApiError> {
+    let from = from.0.map_err(ApiError::BadRequest)?;
+    let to = to.0.map_err(ApiError::BadRequest)?;
+    check_range("from", from, "to", to)?;
+
+    Ok((from, to))
+}
+
+/// Like [`date_range`], but either end may be left open.
+pub fn optional_date_range(
+    from: (&str, Option<QueryDate>),
+    to: (&str, Option<QueryDate>),
+) -> Result<(Option<chrono::NaiveDate>, Option<chrono::NaiveDate>), ApiError> {
+    let from_date = QueryDate::optional(from.1)?;
+    let to_date = QueryDate::optional(to.1)?;
+    if let (Some(from_date), Some(to_date)) = (from_date, to_date) {
+        check_range(from.0, from_date, to.0, to_date)?;
+    }
+
+    Ok((from_date, to_date))
+}
+
+fn check_range(
+    from_name: &str,
+    from: chrono::NaiveDate,
+    to_name: &str,
+    to: chrono::NaiveDate,
+) -> Result<(), ApiError> {
+    if from > to {
+        return Err(ApiError::BadRequest(format!(
+            "Invalid date range: '{}' ({}) is after '{}' ({}).",
+            from_name, from, to_name, to
+        )));
+    }
+
+    Ok(())
+}
+
+/// Validates 1-based `page`/`per_page` query parameters, applying the defaults.
+pub fn pagination(
+    page: Option<usize>,
+    per_page: Option<usize>,
+) -> Result<(usize, usize), ApiError> {
+    let page = page.unwrap_or(1);
+    let per_page = per_page.unwrap_or(DEFAULT_PER_PAGE);
+
+    if page == 0 {
+        return Err(ApiError::BadRequest(String::from(
+            "Invalid 'page': pages start at 1.",
+        )));
+    }
+    if per_page == 0 || per_page > MAX_PER_PAGE {
+        return Err(ApiError::BadRequest(format!(
+            "Invalid 'per_page' {}, expected 1 to {}.",
+            per_page, MAX_PER_PAGE
+        )));
+    }
+    // The rows skipped must fit the database's signed 64-bit OFFSET.
+    let offset = (page - 1).checked_mul(per_page);
+    if offset.is_none_or(|offset| offset as u64 > i64::MAX as u64) {
+        return Err(ApiError::BadRequest(format!(
+            "Invalid 'page' {}: it is too far past the last page.",
+            page
+        )));
+    }
+
+    Ok((page, per_page))
+}
+
+/// Validates a `sort`/`order` pair against the columns of an entity.
+pub fn sorting<C: std::str::FromStr>(
+    sort: Option<&str>,
+    order: Option<&str>,
+    default: (C, sea_orm::Order),
+) -> Result<(C, sea_orm::Order), ApiError> {
+    let (column, default_order) = match sort {
+        Some(sort) => (
+            C::from_str(sort)
+                .map_err(|_| ApiError::BadRequest(format!("Invalid 'sort' column '{}'.", sort)))?,
+            sea_orm::Order::Asc,
+        ),
+        None => default,
+    };
+    let order = match order {
+        None => default_order,
+        Some("asc") => sea_orm::Order::Asc,
+        Some("desc") => sea_orm::Order::Desc,
+        Some(order) => {
+            return Err(ApiError::BadRequest(format!(
+                "Invalid 'order' '{}', expected 'asc' or 'desc'.",
+                order
+            )))
+        }
+    };
+
+    Ok((column, order))
+}
+
+/// One page of a listing, with the total number of matches across all pages.
+#[derive(Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: usize,
+    pub page: usize,
+    pub per_page: usize,
+}
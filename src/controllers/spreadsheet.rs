@@ -0,0 +1,510 @@
+pub mod date;
+pub mod export;
+
+use super::error::ApiError;
+use crate::models::item;
+use calamine::Reader;
+use rocket::{
+    async_trait,
+    data::ByteUnit,
+    form::{self, DataField, FromFormField},
+    http::ContentType,
+    serde::{Deserialize, Serialize},
+    FromForm,
+};
+use sea_orm::{DatabaseTransaction, DbErr};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{Cursor, Read},
+};
+
+/// Spreadsheet import settings, read from the `import` table of `Rocket.toml`.
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ImportConfig {
+    /// Extra header names per field, on top of the built-in ones.
+    #[serde(default)]
+    pub aliases: HashMap<String, Vec<String>>,
+    /// Named mappings from field to header, selected per upload.
+    #[serde(default)]
+    pub profiles: HashMap<String, HashMap<String, String>>,
+    /// Largest spreadsheet accepted for import; larger uploads get a 413.
+    #[serde(default = "ImportConfig::default_max_upload_size")]
+    pub max_upload_size: ByteUnit,
+}
+
+impl ImportConfig {
+    fn default_max_upload_size() -> ByteUnit {
+        ByteUnit::Mebibyte(10)
+    }
+}
+
+impl Default for ImportConfig {
+    fn default() -> Self {
+        ImportConfig {
+            aliases: HashMap::new(),
+            profiles: HashMap::new(),
+            max_upload_size: ImportConfig::default_max_upload_size(),
+        }
+    }
+}
+
+/// A column an importer reads, found by any of its header names.
+pub struct Field {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub required: bool,
+}
+
+/// Where each field was found in a sheet.
+pub struct Columns(HashMap<&'static str, u32>);
+
+impl Columns {
+    /// The trimmed text of `field` in `row`, empty if the sheet lacks the column.
+    pub fn value(&self, sheet: &Sheet, field: &str, row: u32) -> String {
+        match self.0.get(field) {
+            Some(col) => sheet.cell(*col, row).trim().to_string(),
+            None => String::new(),
+        }
+    }
+}
+
+fn normalize_header(header: &str) -> String {
+    header
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '_')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Locates `fields` by the header names in the first row of `sheet`, using
+/// the mapping profile if one is given and the aliases otherwise.
+///
+/// A sheet whose header row names none of the fields is read in the order
+/// of `fields`, as sheets were before headers were read.
+pub fn map_columns(
+    sheet: &Sheet,
+    fields: &'static [Field],
+    config: &ImportConfig,
+    profile: Option<&str>,
+) -> Result<Columns, ApiError> {
+    let profile = match profile {
+        Some(name) => match config.profiles.get(name) {
+            Some(profile) => Some(profile),
+            None => {
+                return Err(ApiError::BadRequest(format!(
+                    "Unknown import profile '{}'.",
+                    name
+                )))
+            }
+        },
+        None => None,
+    };
+
+    let headers: Vec<(u32, String)> = (1..=sheet.highest_column())
+        .map(|col| (col, normalize_header(sheet.cell(col, 1))))
+        .collect();
+
+    let mut columns = HashMap::new();
+    for field in fields {
+        let names: Vec<String> = match profile.and_then(|profile| profile.get(field.name)) {
+            Some(header) => vec![normalize_header(header)],
+            None => std::iter::once(field.name)
+                .chain(field.aliases.iter().copied())
+                .chain(
+                    config
+                        .aliases
+                        .get(field.name)
+                        .into_iter()
+                        .flatten()
+                        .map(String::as_str),
+                )
+                .map(normalize_header)
+                .collect(),
+        };
+        if let Some((col, _)) = headers.iter().find(|(_, header)| names.contains(header)) {
+            columns.insert(field.name, *col);
+        }
+    }
+
+    if columns.is_empty() {
+        return Ok(Columns(
+            fields
+                .iter()
+                .zip(1..)
+                .map(|(field, col)| (field.name, col))
+                .collect(),
+        ));
+    }
+
+    let missing: Vec<&str> = fields
+        .iter()
+        .filter(|field| field.required && !columns.contains_key(field.name))
+        .map(|field| field.name)
+        .collect();
+    if !missing.is_empty() {
+        return Err(ApiError::BadRequest(format!(
+            "The header row has no column for: {}.",
+            missing.join(", ")
+        )));
+    }
+
+    Ok(Columns(columns))
+}
+
+/// Finds the item a row refers to: by SKU when the row has one, and else by
+/// name and manufacturer, narrowed down by specification when given. Items
+/// with another SKU never match by name, and archived items not at all.
+///
+/// Returns `None` when no item matches, and an error message when several
+/// do, as a guess between them would draw from or add to the wrong one, or
+/// when the SKU is an archived item's.
+pub fn match_item<'a>(
+    items: &'a [item::Model],
+    sku: &str,
+    name: &str,
+    manufacturer: &str,
+    specification: &str,
+) -> Result<Option<&'a item::Model>, String> {
+    if !sku.is_empty() {
+        if let Some(item) = items.iter().find(|item| item.sku.as_deref() == Some(sku)) {
+            if item.archived {
+                return Err(format!(
+                    "Item {} with SKU '{}' is archived; restore it first.",
+                    item.id, sku
+                ));
+            }
+            return Ok(Some(item));
+        }
+    }
+
+    let matched: Vec<&item::Model> = items
+        .iter()
+        .filter(|item| !item.archived)
+        .filter(|item| item.sku.is_none() || sku.is_empty())
+        .filter(|item| item.name == name && item.manufacturer == manufacturer)
+        .filter(|item| {
+            specification.is_empty()
+                || item.specification.as_deref().unwrap_or_default() == specification
+        })
+        .collect();
+    match matched[..] {
+        [] => Ok(None),
+        [item] => Ok(Some(item)),
+        _ => Err(format!(
+            "{} items match '{}' from '{}'; give a SKU or specification to tell them apart.",
+            matched.len(),
+            name,
+            manufacturer
+        )),
+    }
+}
+
+/// An uploaded file, read into memory up to the configured maximum size.
+pub struct UploadedFile {
+    name: Option<String>,
+    content_type: ContentType,
+    bytes: Vec<u8>,
+}
+
+#[async_trait]
+impl<'r> FromFormField<'r> for UploadedFile {
+    async fn from_data(field: DataField<'r, '_>) -> form::Result<'r, Self> {
+        let limit = field
+            .request
+            .rocket()
+            .state::<ImportConfig>()
+            .map_or_else(ImportConfig::default_max_upload_size, |config| {
+                config.max_upload_size
+            });
+        let bytes = field.data.open(limit).into_bytes().await?;
+        if !bytes.is_complete() {
+            return Err((None, Some(limit)).into());
+        }
+
+        Ok(UploadedFile {
+            name: field
+                .file_name
+                .map(|name| name.dangerous_unsafe_unsanitized_raw().to_string()),
+            content_type: field.content_type,
+            bytes: bytes.into_inner(),
+        })
+    }
+}
+
+#[derive(FromForm)]
+pub struct Upload {
+    pub file: UploadedFile,
+}
+
+/// A spreadsheet row that could not be imported.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RowError {
+    /// 1-based row number, as shown by spreadsheet programs.
+    pub row: u32,
+    /// Name of the offending column, when the error is about a single cell.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<String>,
+    pub message: String,
+}
+
+/// The outcome of an import, row by row. Both importers answer with it.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ImportReport<R> {
+    pub dry_run: bool,
+    /// Whether the rows were recorded; nothing is on a dry run or if any row
+    /// fails.
+    pub committed: bool,
+    /// The rows imported, or that would be once every row is valid.
+    pub rows: Vec<R>,
+    pub errors: Vec<RowError>,
+}
+
+impl<R> ImportReport<R> {
+    /// Commits the import's transaction if every row is valid and this is
+    /// not a dry run, and rolls it back otherwise.
+    pub async fn finish(
+        transaction: DatabaseTransaction,
+        dry_run: bool,
+        rows: Vec<R>,
+        errors: Vec<RowError>,
+    ) -> Result<Self, DbErr> {
+        let committed = !dry_run && errors.is_empty();
+        if committed {
+            transaction.commit().await?;
+        } else {
+            transaction.rollback().await?;
+        }
+
+        Ok(ImportReport {
+            dry_run,
+            committed,
+            rows,
+            errors,
+        })
+    }
+}
+
+/// The first sheet of an uploaded spreadsheet, as the text of its cells.
+///
+/// Only cells with text are kept, so a sheet with a single cell far from A1
+/// stays small.
+pub struct Sheet {
+    /// Text by 1-based row and column.
+    cells: BTreeMap<(u32, u32), String>,
+    highest_row: u32,
+    highest_column: u32,
+    /// Whether date serials count from 1904 instead of 1900.
+    pub date1904: bool,
+}
+
+impl Sheet {
+    /// Collects the cells given by 1-based row and column, dropping empty ones.
+    fn new(cells: impl IntoIterator<Item = ((u32, u32), String)>, date1904: bool) -> Sheet {
+        let cells: BTreeMap<_, _> = cells
+            .into_iter()
+            .filter(|(_, text)| !text.is_empty())
+            .collect();
+        let highest_row = cells.keys().next_back().map_or(0, |&(row, _)| row);
+        let highest_column = cells.keys().map(|&(_, col)| col).max().unwrap_or(0);
+
+        Sheet {
+            cells,
+            highest_row,
+            highest_column,
+            date1904,
+        }
+    }
+
+    pub fn highest_row(&self) -> u32 {
+        self.highest_row
+    }
+
+    pub fn highest_column(&self) -> u32 {
+        self.highest_column
+    }
+
+    /// Whether every cell of a 1-based row is empty or whitespace, as rows
+    /// left over below the data often are.
+    pub fn is_blank_row(&self, row: u32) -> bool {
+        self.cells
+            .range((row, 0)..=(row, u32::MAX))
+            .all(|(_, text)| text.trim().is_empty())
+    }
+
+    /// The text of a cell, by 1-based column and row.
+    pub fn cell(&self, col: u32, row: u32) -> &str {
+        self.cells.get(&(row, col)).map_or("", String::as_str)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Xlsx,
+    Ods,
+    Csv,
+}
+
+impl Format {
+    /// Tells the format from the upload's content type or file extension,
+    /// falling back to its content.
+    fn detect(file: &UploadedFile) -> Format {
+        let from_extension = file
+            .name
+            .as_deref()
+            .and_then(|name| name.rsplit_once('.'))
+            .and_then(|(_, extension)| ContentType::from_extension(&extension.to_lowercase()));
+
+        for content_type in [Some(file.content_type.clone()), from_extension]
+            .into_iter()
+            .flatten()
+        {
+            match (content_type.top().as_str(), content_type.sub().as_str()) {
+                (_, "vnd.openxmlformats-officedocument.spreadsheetml.sheet") => {
+                    return Format::Xlsx
+                }
+                (_, "vnd.oasis.opendocument.spreadsheet") => return Format::Ods,
+                ("text", "csv") => return Format::Csv,
+                _ => (),
+            }
+        }
+
+        // Both xlsx and ods are zip archives; ods names itself in `mimetype`.
+        let bytes = &file.bytes;
+        if !bytes.starts_with(b"PK") {
+            Format::Csv
+        } else if bytes[..bytes.len().min(80)]
+            .windows(11)
+            .any(|window| window == b"opendocumen")
+        {
+            Format::Ods
+        } else {
+            Format::Xlsx
+        }
+    }
+}
+
+/// Reads the first sheet of an uploaded xlsx, ods or csv file.
+pub fn read_sheet(file: &UploadedFile) -> Result<Sheet, ApiError> {
+    match Format::detect(file) {
+        Format::Xlsx => read_xlsx(&file.bytes),
+        Format::Ods => read_ods(&file.bytes),
+        Format::Csv => read_csv(&file.bytes),
+    }
+}
+
+fn read_xlsx(bytes: &[u8]) -> Result<Sheet, ApiError> {
+    let invalid = || ApiError::BadRequest(String::from("The upload is not a valid xlsx file."));
+
+    let book = umya_spreadsheet::reader::xlsx::read_reader(Cursor::new(bytes), true)
+        .map_err(|_| invalid())?;
+    let sheet = book.get_sheet(&0).map_err(|_| invalid())?;
+
+    let cells = sheet.get_cell_collection().into_iter().map(|cell| {
+        let coordinate = cell.get_coordinate();
+        (
+            (*coordinate.get_row_num(), *coordinate.get_col_num()),
+            cell.get_value().into_owned(),
+        )
+    });
+
+    Ok(Sheet::new(cells, uses_1904_dates(bytes)))
+}
+
+fn read_ods(bytes: &[u8]) -> Result<Sheet, ApiError> {
+    let invalid = || ApiError::BadRequest(String::from("The upload is not a valid ods file."));
+
+    let mut book: calamine::Ods<_> =
+        calamine::open_workbook_from_rs(Cursor::new(bytes)).map_err(|_| invalid())?;
+    let range = match book.worksheet_range_at(0) {
+        Some(Ok(range)) => range,
+        _ => return Err(invalid()),
+    };
+
+    // Cells are addressed from A1, even if the used range starts further in.
+    let (first_row, first_col) = range.start().unwrap_or_default();
+    let cells = range.used_cells().map(|(row, col, value)| {
+        (
+            (first_row + row as u32 + 1, first_col + col as u32 + 1),
+            value.to_string(),
+        )
+    });
+
+    // OpenDocument stores dates as ISO text, not serials.
+    Ok(Sheet::new(cells, false))
+}
+
+/// Reads comma, semicolon or tab separated text, in UTF-8 or else GBK.
+fn read_csv(bytes: &[u8]) -> Result<Sheet, ApiError> {
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => std::borrow::Cow::Borrowed(text.trim_start_matches('\u{feff}')),
+        Err(_) => {
+            let (text, _, malformed) = encoding_rs::GBK.decode(bytes);
+            if malformed {
+                return Err(ApiError::BadRequest(String::from(
+                    "The upload is neither UTF-8 nor GBK text.",
+                )));
+            }
+            text
+        }
+    };
+
+    let header = text.lines().next().unwrap_or_default();
+    let delimiter = [b',', b';', b'\t']
+        .into_iter()
+        .max_by_key(|delimiter| header.bytes().filter(|b| b == delimiter).count())
+        .unwrap_or(b',');
+
+    let mut cells = Vec::new();
+    let records = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(text.as_bytes())
+        .into_records();
+    for (row, record) in (1..).zip(records) {
+        let record = record
+            .map_err(|err| ApiError::BadRequest(format!("The upload is not valid CSV: {}", err)))?;
+        for (col, text) in (1..).zip(record.iter()) {
+            cells.push(((row, col), unquote_formula(text)));
+        }
+    }
+
+    Ok(Sheet::new(cells, false))
+}
+
+/// Undoes the `'` that exports put before text starting with one of the
+/// [`export::FORMULA_PREFIXES`].
+fn unquote_formula(text: &str) -> String {
+    match text.strip_prefix('\'') {
+        Some(rest) if rest.starts_with(export::FORMULA_PREFIXES) => rest.to_string(),
+        _ => text.to_string(),
+    }
+}
+
+/// Whether an xlsx workbook uses the 1904 date system, which
+/// umya-spreadsheet does not expose. It is the `date1904` attribute of
+/// `workbookPr` in `xl/workbook.xml`.
+fn uses_1904_dates(bytes: &[u8]) -> bool {
+    let read_workbook_xml = || -> Option<String> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).ok()?;
+        let mut xml = String::new();
+        archive
+            .by_name("xl/workbook.xml")
+            .ok()?
+            .read_to_string(&mut xml)
+            .ok()?;
+        Some(xml)
+    };
+
+    read_workbook_xml()
+        .and_then(|xml| {
+            let start = xml.find("workbookPr")?;
+            let end = start + xml[start..].find('>')?;
+            let element = &xml[start..end];
+            Some(element.contains("date1904=\"1\"") || element.contains("date1904=\"true\""))
+        })
+        .unwrap_or(false)
+}
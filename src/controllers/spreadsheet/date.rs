@@ -0,0 +1,159 @@
+//! Dates in spreadsheet cells.
+//!
+//! Excel stores dates as serial day numbers, counted from 1900 or, in
+//! workbooks made with the 1904 date system, from 1904. The 1900 system
+//! carries Lotus 1-2-3's bug of treating 1900 as a leap year: serial 60 is
+//! the nonexistent 1900-02-29, and every later serial is one day ahead.
+//! Dates typed as text are left as text by Excel.
+
+use chrono::{Duration, NaiveDate};
+
+/// Largest serial Excel accepts, 9999-12-31 in the 1900 system.
+const MAX_SERIAL: f64 = 2_958_465.0;
+
+const TEXT_FORMATS: [&str; 4] = ["%Y-%m-%d", "%Y/%m/%d", "%Y.%m.%d", "%Y年%m月%d日"];
+
+/// Reads the date in a cell's text, either a serial number, whose time of
+/// day is ignored, or a typed date such as `2024-03-01` or `2024/3/1`.
+pub fn parse(value: &str, date1904: bool) -> Option<NaiveDate> {
+    let value = value.trim();
+
+    // Eight digits are a compact YYYYMMDD date; no serial is that large.
+    if value.len() == 8 && value.bytes().all(|b| b.is_ascii_digit()) {
+        return NaiveDate::parse_from_str(value, "%Y%m%d").ok();
+    }
+    if let Ok(serial) = value.parse::<f64>() {
+        return from_serial(serial, date1904);
+    }
+
+    // Drop a time of day, as in `2024-03-01 08:30` or `2024-03-01T08:30:00`.
+    let date = value.split([' ', 'T']).next().unwrap_or_default();
+    TEXT_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(date, format).ok())
+}
+
+/// Converts a serial day number to a date, ignoring any time of day.
+pub fn from_serial(serial: f64, date1904: bool) -> Option<NaiveDate> {
+    if !serial.is_finite() || serial > MAX_SERIAL {
+        return None;
+    }
+    let days = serial.floor() as i64;
+
+    if date1904 {
+        if days < 0 {
+            return None;
+        }
+        return Some(NaiveDate::from_ymd(1904, 1, 1) + Duration::days(days));
+    }
+    match days {
+        i64::MIN..=0 => None,
+        1..=59 => Some(NaiveDate::from_ymd(1899, 12, 31) + Duration::days(days)),
+        // 1900-02-29, which never existed.
+        60 => None,
+        _ => Some(NaiveDate::from_ymd(1899, 12, 30) + Duration::days(days)),
+    }
+}
+
+/// Converts a date to its serial day number in the 1900 system, which is
+/// what new workbooks use.
+pub fn to_serial(date: NaiveDate) -> f64 {
+    let days = if date < NaiveDate::from_ymd(1900, 3, 1) {
+        (date - NaiveDate::from_ymd(1899, 12, 31)).num_days()
+    } else {
+        (date - NaiveDate::from_ymd(1899, 12, 30)).num_days()
+    };
+    days as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ymd(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+        Some(NaiveDate::from_ymd(year, month, day))
+    }
+
+    #[test]
+    fn serials_in_the_1900_system() {
+        assert_eq!(from_serial(1.0, false), ymd(1900, 1, 1));
+        assert_eq!(from_serial(59.0, false), ymd(1900, 2, 28));
+        assert_eq!(from_serial(60.0, false), None);
+        assert_eq!(from_serial(61.0, false), ymd(1900, 3, 1));
+        assert_eq!(from_serial(36526.0, false), ymd(2000, 1, 1));
+        assert_eq!(from_serial(44927.0, false), ymd(2023, 1, 1));
+        assert_eq!(from_serial(45352.0, false), ymd(2024, 3, 1));
+        assert_eq!(from_serial(MAX_SERIAL, false), ymd(9999, 12, 31));
+    }
+
+    #[test]
+    fn serials_in_the_1904_system() {
+        assert_eq!(from_serial(0.0, true), ymd(1904, 1, 1));
+        assert_eq!(from_serial(43465.0, true), ymd(2023, 1, 1));
+        assert_eq!(from_serial(43890.0, true), ymd(2024, 3, 1));
+    }
+
+    #[test]
+    fn serials_round_trip() {
+        for date in [
+            ymd(1900, 1, 1),
+            ymd(1900, 2, 28),
+            ymd(1900, 3, 1),
+            ymd(2024, 3, 1),
+            ymd(9999, 12, 31),
+        ] {
+            let date = date.unwrap();
+            assert_eq!(from_serial(to_serial(date), false), Some(date));
+        }
+        assert_eq!(to_serial(NaiveDate::from_ymd(2024, 3, 1)), 45352.0);
+    }
+
+    #[test]
+    fn time_fractions_are_dropped() {
+        assert_eq!(from_serial(45352.75, false), ymd(2024, 3, 1));
+        assert_eq!(parse("45352.999988", false), ymd(2024, 3, 1));
+        assert_eq!(parse("43890.5", true), ymd(2024, 3, 1));
+    }
+
+    #[test]
+    fn out_of_range_serials() {
+        assert_eq!(from_serial(0.0, false), None);
+        assert_eq!(from_serial(-1.0, false), None);
+        assert_eq!(from_serial(-1.0, true), None);
+        assert_eq!(from_serial(MAX_SERIAL + 1.0, false), None);
+        assert_eq!(from_serial(f64::NAN, false), None);
+    }
+
+    #[test]
+    fn text_dates() {
+        for text in [
+            "2024-03-01",
+            "2024/3/1",
+            "2024/03/01",
+            "2024.3.1",
+            "2024年3月1日",
+            "20240301",
+            " 2024-03-01 ",
+            "2024-03-01 08:30",
+            "2024-03-01T08:30:00",
+        ] {
+            assert_eq!(parse(text, false), ymd(2024, 3, 1), "{}", text);
+        }
+        // Typed dates do not depend on the date system.
+        assert_eq!(parse("2024-03-01", true), ymd(2024, 3, 1));
+    }
+
+    #[test]
+    fn invalid_text() {
+        for text in [
+            "",
+            "tomorrow",
+            "2024-02-30",
+            "2024/13/1",
+            "20241301",
+            "1/3/2024",
+        ] {
+            assert_eq!(parse(text, false), None, "{}", text);
+        }
+    }
+}
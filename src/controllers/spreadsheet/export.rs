@@ -0,0 +1,264 @@
+//! Spreadsheet downloads.
+//!
+//! Exported sheets have a header row of the importers' field names and
+//! dates that read back as dates, so a downloaded sheet can be edited and
+//! uploaded again.
+
+use super::date;
+use crate::controllers::error::ApiError;
+use chrono::NaiveDate;
+use rocket::{
+    http::{ContentType, Header},
+    response::{self, Responder},
+    Request, Response,
+};
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    okapi::{
+        openapi3::{MediaType, Responses},
+        schemars::schema::{InstanceType, SchemaObject},
+    },
+    response::OpenApiResponderInner,
+    util::add_content_response,
+};
+use std::io::Cursor;
+use umya_spreadsheet::NumberingFormat;
+
+/// A cell of an exported sheet.
+pub enum Value {
+    Text(String),
+    Number(f64),
+    Date(NaiveDate),
+}
+
+impl From<String> for Value {
+    fn from(text: String) -> Self {
+        Value::Text(text)
+    }
+}
+
+impl From<Option<String>> for Value {
+    fn from(text: Option<String>) -> Self {
+        Value::Text(text.unwrap_or_default())
+    }
+}
+
+impl From<i32> for Value {
+    fn from(number: i32) -> Self {
+        Value::Number(number.into())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(number: i64) -> Self {
+        Value::Number(number as f64)
+    }
+}
+
+impl From<f32> for Value {
+    /// Goes through the shortest decimal text of the price, so that 2.3 is
+    /// not written as 2.299999952316284.
+    fn from(number: f32) -> Self {
+        Value::Number(number.to_string().parse().unwrap_or_default())
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Text(value.to_string())
+    }
+}
+
+impl From<NaiveDate> for Value {
+    fn from(date: NaiveDate) -> Self {
+        Value::Date(date)
+    }
+}
+
+/// Columns of the stock-in and stock-out period reports.
+pub const REPORT_HEADERS: &[&str] = &[
+    "item_id",
+    "name",
+    "specification",
+    "unit",
+    "manufacturer",
+    "price",
+    "number",
+];
+
+/// Leading characters that make spreadsheet programs read a csv cell as a
+/// formula, or that some of them skip before looking for one. Text starting
+/// with one of them is exported behind a `'`.
+pub const FORMULA_PREFIXES: &[char] = &['=', '+', '-', '@', '\t', '\r'];
+
+/// Rows to export below a header row.
+pub struct Table {
+    /// File name of the download, without extension.
+    pub name: String,
+    pub headers: &'static [&'static str],
+    pub rows: Vec<Vec<Value>>,
+}
+
+#[derive(Clone, Copy)]
+pub enum ExportFormat {
+    Xlsx,
+    Csv,
+}
+
+impl ExportFormat {
+    /// Reads the `format` query parameter, xlsx when absent.
+    pub fn parse(format: Option<&str>) -> Result<ExportFormat, ApiError> {
+        match format.map(str::to_lowercase).as_deref() {
+            None | Some("xlsx") => Ok(ExportFormat::Xlsx),
+            Some("csv") => Ok(ExportFormat::Csv),
+            Some(format) => Err(ApiError::BadRequest(format!(
+                "Unknown export format '{}', expected xlsx or csv.",
+                format
+            ))),
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Xlsx => "xlsx",
+            ExportFormat::Csv => "csv",
+        }
+    }
+
+    fn content_type(self) -> ContentType {
+        match self {
+            ExportFormat::Xlsx => ContentType::new(
+                "application",
+                "vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            ),
+            ExportFormat::Csv => ContentType::new("text", "csv").with_params(("charset", "utf-8")),
+        }
+    }
+}
+
+/// A spreadsheet file, sent as an attachment.
+pub struct Download {
+    filename: String,
+    format: ExportFormat,
+    bytes: Vec<u8>,
+}
+
+/// Writes `table` as a file of the given format.
+pub fn export(table: Table, format: ExportFormat) -> Result<Download, ApiError> {
+    let bytes = match format {
+        ExportFormat::Xlsx => write_xlsx(&table)?,
+        ExportFormat::Csv => write_csv(&table)?,
+    };
+
+    Ok(Download {
+        filename: format!("{}.{}", table.name, format.extension()),
+        format,
+        bytes,
+    })
+}
+
+fn write_xlsx(table: &Table) -> Result<Vec<u8>, ApiError> {
+    let mut book = umya_spreadsheet::new_file();
+    let sheet = book
+        .get_sheet_mut(&0)
+        .map_err(|_| ApiError::Internal(String::from("Error occurs while writing the sheet.")))?;
+
+    for (col, header) in (1..).zip(table.headers) {
+        sheet
+            .get_cell_by_column_and_row_mut(&col, &1)
+            .set_value_from_string(*header);
+        sheet
+            .get_style_by_column_and_row_mut(&col, &1)
+            .get_font_mut()
+            .set_bold(true);
+    }
+    for (row, values) in (2..).zip(&table.rows) {
+        for (col, value) in (1..).zip(values) {
+            let cell = sheet.get_cell_by_column_and_row_mut(&col, &row);
+            match value {
+                // A string-typed cell, which is never evaluated as a formula.
+                Value::Text(text) => {
+                    cell.set_value_from_string(text.as_str());
+                }
+                Value::Number(number) => {
+                    cell.get_cell_value_mut().set_value_from_numberic(*number);
+                }
+                Value::Date(date) => {
+                    cell.get_cell_value_mut()
+                        .set_value_from_numberic(date::to_serial(*date));
+                    cell.get_style_mut()
+                        .get_number_format_mut()
+                        .set_format_code(NumberingFormat::FORMAT_DATE_YYYYMMDD);
+                }
+            }
+        }
+    }
+
+    let mut bytes = Cursor::new(Vec::new());
+    umya_spreadsheet::writer::xlsx::write_writer(&book, &mut bytes).map_err(|_| {
+        ApiError::Internal(String::from("Error occurs while writing the xlsx file."))
+    })?;
+
+    Ok(bytes.into_inner())
+}
+
+/// Writes UTF-8 with a byte order mark, without which Excel reads the text
+/// in the local code page. Text that would read as a formula is quoted with
+/// a leading `'`.
+fn write_csv(table: &Table) -> Result<Vec<u8>, ApiError> {
+    let failed = |_| ApiError::Internal(String::from("Error occurs while writing the csv file."));
+
+    let mut writer = csv::Writer::from_writer(b"\xef\xbb\xbf".to_vec());
+    writer.write_record(table.headers).map_err(failed)?;
+    for values in &table.rows {
+        writer
+            .write_record(values.iter().map(|value| match value {
+                Value::Text(text) if text.starts_with(FORMULA_PREFIXES) => format!("'{}", text),
+                Value::Text(text) => text.clone(),
+                Value::Number(number) => number.to_string(),
+                Value::Date(date) => date.format("%Y-%m-%d").to_string(),
+            }))
+            .map_err(failed)?;
+    }
+
+    writer
+        .into_inner()
+        .map_err(|_| ApiError::Internal(String::from("Error occurs while writing the csv file.")))
+}
+
+impl<'r> Responder<'r, 'static> for Download {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .header(self.format.content_type())
+            .header(Header::new(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", self.filename),
+            ))
+            .sized_body(self.bytes.len(), Cursor::new(self.bytes))
+            .ok()
+    }
+}
+
+impl OpenApiResponderInner for Download {
+    fn responses(_: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        let mut responses = Responses::default();
+        for format in [ExportFormat::Xlsx, ExportFormat::Csv] {
+            let content_type = format.content_type();
+            add_content_response(
+                &mut responses,
+                200,
+                format!("{}/{}", content_type.top(), content_type.sub()),
+                MediaType {
+                    schema: Some(SchemaObject {
+                        instance_type: Some(InstanceType::String.into()),
+                        format: Some(String::from("binary")),
+                        ..SchemaObject::default()
+                    }),
+                    ..MediaType::default()
+                },
+            )?;
+        }
+
+        Ok(responses)
+    }
+}
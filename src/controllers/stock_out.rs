@@ -1,80 +1,333 @@
-use crate::dao;
-use crate::models::stock_out;
-use rocket::{
-    get, http, post,
-    response::status::Custom,
-    serde::json::{json, Json, Value},
-    State,
+use super::auth::{Clerk, Manager, Viewer};
+use super::error::ApiError;
+use super::params::{date_range, QueryDate};
+use super::spreadsheet::export::{export, Download, ExportFormat, Table, REPORT_HEADERS};
+use super::spreadsheet::{
+    date, map_columns, match_item, read_sheet, Field, ImportConfig, ImportReport, RowError, Upload,
 };
+use crate::dao::{self, audit::AuditContext, stock_out::StockOutError};
+use crate::models::{stock_out, stock_out_batch};
+use rocket::{delete, form::Form, get, post, serde::json::Json, serde::Serialize, State};
 use rocket_okapi::openapi;
-use sea_orm::DatabaseConnection;
+use sea_orm::{DatabaseConnection, TransactionTrait};
 
 #[openapi(tag = "stock-out")]
 #[get("/stock-out-and-items?<from>&<to>")]
 pub async fn get_stock_out_and_items(
     db: &State<DatabaseConnection>,
-    from: String,
-    to: String,
-) -> Result<Json<Vec<dao::stock_out::StockOutAndItem>>, Custom<Value>> {
+    _user: Viewer,
+    from: QueryDate,
+    to: QueryDate,
+) -> Result<Json<Vec<dao::stock_out::StockOutAndItem>>, ApiError> {
+    let (from, to) = date_range(from, to)?;
     let stock_outs =
-        dao::stock_out::get_stock_out_and_items(db as &DatabaseConnection, from, to).await;
-
-    match stock_outs {
-        Ok(stock_outs) => Ok(Json(stock_outs)),
-        Err(_) => Err(Custom(
-            http::Status::InternalServerError,
-            json!({
-              "error": {
-                "code": 500,
-                "reason": "Internal Server Error",
-                "description": "Error occurs while getting stock_out records from the database."
-              }
-            }),
-        )),
-    }
+        dao::stock_out::get_stock_out_and_items(db as &DatabaseConnection, from, to).await?;
+
+    Ok(Json(stock_outs))
 }
 
+/// Downloads the stock-out report for a period as an xlsx (default) or csv
+/// `format` sheet.
 #[openapi(tag = "stock-out")]
-#[post("/stock-out", data = "<stock_out>")]
-pub async fn insert_or_update_stock_out(
+#[get("/stock-out-and-items/export?<from>&<to>&<format>")]
+pub async fn export_stock_out_and_items(
     db: &State<DatabaseConnection>,
+    _user: Viewer,
+    from: QueryDate,
+    to: QueryDate,
+    format: Option<String>,
+) -> Result<Download, ApiError> {
+    let (from, to) = date_range(from, to)?;
+    let format = ExportFormat::parse(format.as_deref())?;
+    let stock_outs = dao::stock_out::get_stock_out_and_items(db, from, to).await?;
+
+    export(
+        Table {
+            name: format!("stock-out-{}-{}", from, to),
+            headers: REPORT_HEADERS,
+            rows: stock_outs
+                .into_iter()
+                .map(|stock_out| {
+                    vec![
+                        stock_out.item_id.into(),
+                        stock_out.name.into(),
+                        stock_out.specification.into(),
+                        stock_out.unit.into(),
+                        stock_out.manufacturer.into(),
+                        stock_out.price.into(),
+                        stock_out.number.into(),
+                    ]
+                })
+                .collect(),
+        },
+        format,
+    )
+}
+
+/// Posts a stock-out and returns its id, recording the logged-in user as its
+/// operator. Stock-outs larger than the item's stock are refused with 409
+/// unless `allow_negative` is set together with a `reason`.
+#[openapi(tag = "stock-out")]
+#[post("/stock-out?<allow_negative>&<reason>", data = "<stock_out>")]
+pub async fn insert_stock_out(
+    db: &State<DatabaseConnection>,
+    user: Clerk,
+    context: AuditContext,
     stock_out: Json<stock_out::Model>,
-) -> Result<(), Custom<Value>> {
-    let result = dao::stock_out::insert_or_update_stock_out(db, stock_out.0).await;
-
-    match result {
-        Ok(_) => Ok(()),
-        Err(_) => Err(Custom(
-            http::Status::InternalServerError,
-            json!({
-              "error": {
-                "code": 500,
-                "reason": "Internal Server Error",
-                "description": "Error occurs while inserting or updating StockOut record."
-              }
-            }),
-        )),
-    }
+    allow_negative: Option<bool>,
+    reason: Option<String>,
+) -> Result<Json<i32>, ApiError> {
+    let override_reason = match (allow_negative.unwrap_or(false), reason) {
+        (false, _) => None,
+        (true, Some(reason)) if !reason.trim().is_empty() => Some(reason),
+        (true, _) => {
+            return Err(ApiError::BadRequest(String::from(
+                "A reason is required when allow_negative is set.",
+            )))
+        }
+    };
+
+    let stock_out = stock_out::Model {
+        operator: Some(user.0.username),
+        ..stock_out.0
+    };
+    let id = dao::stock_out::insert_stock_out(db, stock_out, override_reason, &context).await?;
+
+    Ok(Json(id))
 }
 
+/// Reverses a stock-out, returning its units to stock.
 #[openapi(tag = "stock-out")]
-#[get("/stock-out/<id>")]
+#[delete("/stock-out/<id>")]
+pub async fn reverse_stock_out(
+    db: &State<DatabaseConnection>,
+    _user: Manager,
+    context: AuditContext,
+    id: i32,
+) -> Result<(), ApiError> {
+    dao::stock_out::reverse_stock_out(db, id, &context).await?;
+
+    Ok(())
+}
+
+/// Lists an item's stock-outs, reversed ones included, latest first.
+#[openapi(tag = "stock-out")]
+#[get("/items/<id>/stock-outs")]
 pub async fn get_stock_out_by_item_id(
     db: &State<DatabaseConnection>,
-    id: u32,
-) -> Result<Json<Vec<stock_out::Model>>, Custom<Value>> {
-    let stock_out = dao::stock_out::get_stock_out_by_item_id(db as &DatabaseConnection, id).await;
-
-    match stock_out {
-        Ok(stock_out) => Ok(Json(stock_out)),
-        Err(_) => Err(Custom(
-            http::Status::NotFound,
-            json!({
-                "error": {
-                "code": 500,
-                "reason": "Internal Server Error",
-                "description": "Error occurs while getting stock_outs records from the database."
-            }}),
-        )),
+    _user: Viewer,
+    id: i32,
+) -> Result<Json<Vec<stock_out::Model>>, ApiError> {
+    let stock_out = dao::stock_out::get_stock_out_by_item_id(db as &DatabaseConnection, id).await?;
+
+    Ok(Json(stock_out))
+}
+
+/// Lists an item's stock-outs, like [`get_stock_out_by_item_id`]. Kept at
+/// the path existing clients use; note that `<id>` is the item's here, not a
+/// stock-out's as in the other `/stock-out/<id>` routes.
+#[openapi(tag = "stock-out")]
+#[get("/stock-out/<id>")]
+pub async fn get_stock_out_by_item_id_at_stock_out(
+    db: &State<DatabaseConnection>,
+    user: Viewer,
+    id: i32,
+) -> Result<Json<Vec<stock_out::Model>>, ApiError> {
+    get_stock_out_by_item_id(db, user, id).await
+}
+
+/// Totals an item's stock-outs per day.
+#[openapi(tag = "stock-out")]
+#[get("/items/<id>/stock-outs/daily")]
+pub async fn get_daily_stock_out_by_item_id(
+    db: &State<DatabaseConnection>,
+    _user: Viewer,
+    id: i32,
+) -> Result<Json<Vec<dao::stock_out::DailyStockOut>>, ApiError> {
+    let daily = dao::stock_out::get_daily_stock_out_by_item_id(db, id).await?;
+
+    Ok(Json(daily))
+}
+
+#[openapi(tag = "stock-out")]
+#[get("/stock-out/<id>/batches")]
+pub async fn get_stock_out_batches(
+    db: &State<DatabaseConnection>,
+    _user: Viewer,
+    id: i32,
+) -> Result<Json<Vec<stock_out_batch::Model>>, ApiError> {
+    let batches = dao::stock_out::get_stock_out_batches(db as &DatabaseConnection, id).await?;
+
+    Ok(Json(batches))
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct StockOutImportRow {
+    row: u32,
+    name: String,
+    manufacturer: String,
+    /// The matched item.
+    item_id: i32,
+    number: i32,
+}
+
+const STOCK_OUT_FIELDS: &[Field] = &[
+    Field {
+        name: "date",
+        aliases: &["日期", "出库日期"],
+        required: true,
+    },
+    Field {
+        name: "name",
+        aliases: &["item", "名称", "品名"],
+        required: true,
+    },
+    Field {
+        name: "manufacturer",
+        aliases: &["厂家", "生产厂家", "生产企业"],
+        required: true,
+    },
+    Field {
+        name: "number",
+        aliases: &["quantity", "qty", "数量"],
+        required: true,
+    },
+    Field {
+        name: "reference",
+        aliases: &["order", "单号", "备注"],
+        required: false,
+    },
+    Field {
+        name: "specification",
+        aliases: &["spec", "规格"],
+        required: false,
+    },
+    Field {
+        name: "sku",
+        aliases: &["barcode", "条码", "条形码", "货号"],
+        required: false,
+    },
+];
+
+/// Imports stock-outs from the first sheet of an xlsx, ods or csv file, one
+/// per row below the header row; blank rows are skipped. Columns are found by
+/// their headers (see [`STOCK_OUT_FIELDS`]), or by the given mapping
+/// `profile`. Items are matched by [`match_item`].
+///
+/// With `dry_run` the import is only previewed and nothing is recorded.
+#[post("/stock-out-from-xlsx?<dry_run>&<profile>", data = "<upload>")]
+pub async fn create_stock_out_from_xlsx(
+    db: &State<DatabaseConnection>,
+    user: Clerk,
+    context: AuditContext,
+    config: &State<ImportConfig>,
+    upload: Form<Upload>,
+    dry_run: Option<bool>,
+    profile: Option<String>,
+) -> Result<Json<ImportReport<StockOutImportRow>>, ApiError> {
+    let dry_run = dry_run.unwrap_or(false);
+    let sheet = &read_sheet(&upload.file)?;
+    let columns = map_columns(sheet, STOCK_OUT_FIELDS, config, profile.as_deref())?;
+    let cell = |field: &str, row: u32| columns.value(sheet, field, row);
+
+    let transaction = db.begin().await?;
+    let items = dao::item::get_items(&transaction).await?;
+
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for row in 2..=sheet.highest_row() {
+        if sheet.is_blank_row(row) {
+            continue;
+        }
+        let errors_before = errors.len();
+        let mut cell_error = |column: &str, message: String| {
+            errors.push(RowError {
+                row,
+                column: Some(column.to_string()),
+                message,
+            })
+        };
+
+        let value = cell("date", row);
+        let date = date::parse(&value, sheet.date1904);
+        if date.is_none() {
+            cell_error("date", format!("'{}' is not a date.", value));
+        }
+        let name = cell("name", row);
+        let manufacturer = cell("manufacturer", row);
+        let number = cell("number", row);
+        let number = match number.parse::<i32>() {
+            Ok(number) => Some(number),
+            Err(_) => {
+                cell_error("number", format!("'{}' is not a whole number.", number));
+                None
+            }
+        };
+        let reference = Some(cell("reference", row)).filter(|r| !r.is_empty());
+
+        let (date, number) = match (date, number) {
+            (Some(date), Some(number)) if errors.len() == errors_before => (date, number),
+            _ => continue,
+        };
+
+        let item = match match_item(
+            &items,
+            &cell("sku", row),
+            &name,
+            &manufacturer,
+            &cell("specification", row),
+        ) {
+            Ok(Some(item)) => item,
+            Ok(None) => {
+                errors.push(RowError {
+                    row,
+                    column: None,
+                    message: format!("No item matches '{}' from '{}'.", name, manufacturer),
+                });
+                continue;
+            }
+            Err(message) => {
+                errors.push(RowError {
+                    row,
+                    column: None,
+                    message,
+                });
+                continue;
+            }
+        };
+
+        let stock_out = stock_out::Model {
+            id: 0,
+            date,
+            number,
+            item_id: item.id,
+            created_at: Default::default(),
+            operator: Some(user.0.username.clone()),
+            reason: None,
+            reference,
+            reversed_at: None,
+        };
+        match dao::stock_out::insert_stock_out_transaction(&transaction, stock_out, None, &context)
+            .await
+        {
+            Ok(_) => rows.push(StockOutImportRow {
+                row,
+                name,
+                manufacturer,
+                item_id: item.id,
+                number,
+            }),
+            Err(StockOutError::Db(err)) => return Err(err.into()),
+            Err(err) => errors.push(RowError {
+                row,
+                column: None,
+                message: ApiError::from(err).body().error.description,
+            }),
+        }
     }
+
+    let report = ImportReport::finish(transaction, dry_run, rows, errors).await?;
+
+    Ok(Json(report))
 }
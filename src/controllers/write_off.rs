@@ -0,0 +1,21 @@
+use super::auth::Viewer;
+use super::error::ApiError;
+use super::params::{date_range, QueryDate};
+use crate::dao;
+use rocket::{get, serde::json::Json, State};
+use rocket_okapi::openapi;
+use sea_orm::DatabaseConnection;
+
+#[openapi(tag = "write-off")]
+#[get("/write-offs?<from>&<to>")]
+pub async fn get_write_offs(
+    db: &State<DatabaseConnection>,
+    _user: Viewer,
+    from: QueryDate,
+    to: QueryDate,
+) -> Result<Json<Vec<dao::write_off::WriteOffAndItem>>, ApiError> {
+    let (from, to) = date_range(from, to)?;
+    let write_offs = dao::write_off::get_write_offs(db, from, to).await?;
+
+    Ok(Json(write_offs))
+}
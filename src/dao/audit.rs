@@ -0,0 +1,98 @@
+use crate::models::{
+    audit,
+    prelude::*,
+    sea_orm_active_enums::{AuditAction, AuditEntity},
+};
+use rocket::serde::{json, Serialize};
+use sea_orm::{
+    ActiveValue, ColumnTrait, Condition, DatabaseConnection, DatabaseTransaction, DbErr,
+    EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
+};
+
+/// Who is making a change, and in which request.
+#[derive(Clone, Debug)]
+pub struct AuditContext {
+    pub actor: String,
+    pub request_id: String,
+}
+
+fn snapshot<T: Serialize>(record: Option<&T>) -> Result<Option<json::Value>, DbErr> {
+    record
+        .map(|record| json::to_value(record).map_err(|err| DbErr::Custom(err.to_string())))
+        .transpose()
+}
+
+/// Appends an audit entry. Call it inside the transaction making the change,
+/// so that the entry is kept exactly when the change is.
+pub async fn record_transaction<T: Serialize>(
+    transaction: &DatabaseTransaction,
+    context: &AuditContext,
+    entity: AuditEntity,
+    entity_id: i32,
+    action: AuditAction,
+    before: Option<&T>,
+    after: Option<&T>,
+) -> Result<(), DbErr> {
+    Audit::insert(audit::ActiveModel {
+        id: ActiveValue::NotSet,
+        created_at: ActiveValue::Set(chrono::Local::now().naive_local()),
+        actor: ActiveValue::Set(context.actor.clone()),
+        request_id: ActiveValue::Set(context.request_id.clone()),
+        entity: ActiveValue::Set(entity),
+        entity_id: ActiveValue::Set(entity_id),
+        action: ActiveValue::Set(action),
+        before: ActiveValue::Set(snapshot(before)?),
+        after: ActiveValue::Set(snapshot(after)?),
+    })
+    .exec(transaction)
+    .await?;
+
+    Ok(())
+}
+
+pub struct AuditFilter {
+    pub entity: Option<AuditEntity>,
+    pub entity_id: Option<i32>,
+    pub actor: Option<String>,
+    /// Day range of the changes, both ends inclusive.
+    pub dates: (Option<chrono::NaiveDate>, Option<chrono::NaiveDate>),
+}
+
+/// Returns one page (1-based) of the matching entries, newest first, and the
+/// total number of matches.
+pub async fn get_audit(
+    db: &DatabaseConnection,
+    filter: AuditFilter,
+    page: usize,
+    per_page: usize,
+) -> Result<(Vec<audit::Model>, usize), DbErr> {
+    let mut condition = Condition::all();
+    if let Some(entity) = filter.entity {
+        condition = condition.add(audit::Column::Entity.eq(entity));
+    }
+    if let Some(entity_id) = filter.entity_id {
+        condition = condition.add(audit::Column::EntityId.eq(entity_id));
+    }
+    if let Some(actor) = filter.actor {
+        condition = condition.add(audit::Column::Actor.eq(actor));
+    }
+    if let Some(from) = filter.dates.0 {
+        condition = condition.add(audit::Column::CreatedAt.gte(from.and_hms(0, 0, 0)));
+    }
+    if let Some(to) = filter.dates.1 {
+        // The last day chrono can represent has no next day to stop before.
+        condition = condition.add(match to.succ_opt() {
+            Some(next) => audit::Column::CreatedAt.lt(next.and_hms(0, 0, 0)),
+            None => audit::Column::CreatedAt.lte(to.and_hms(23, 59, 59)),
+        });
+    }
+
+    let paginator = Audit::find()
+        .filter(condition)
+        .order_by_desc(audit::Column::Id)
+        .paginate(db, per_page);
+    let total = paginator.num_items().await?;
+    let entries = paginator.fetch_page(page - 1).await?;
+
+    Ok((entries, total))
+}
@@ -1,16 +1,26 @@
-use crate::models::{batch, item, prelude::*};
+use super::audit::{self, AuditContext};
+use super::db::{
+    bigint_type, change_stock, check_version, contains_ignore_case, StockOverflow, VersionConflict,
+};
+use super::item::save_item_transaction;
+use super::write_off::write_off_transaction;
+use crate::models::{
+    batch, item,
+    prelude::*,
+    sea_orm_active_enums::{AuditAction, AuditEntity, WriteOffReason},
+};
 use sea_orm::{
-    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection,
-    DatabaseTransaction, DbBackend, DbErr, EntityTrait, FromQueryResult, QueryFilter, QuerySelect,
-    Statement, TransactionTrait,
+    sea_query::Expr, ActiveValue, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection,
+    DatabaseTransaction, DbErr, EntityTrait, FromQueryResult, JoinType, Order, PaginatorTrait,
+    QueryFilter, QueryOrder, QuerySelect, RelationTrait, SelectModel, Selector, TransactionTrait,
 };
 
 #[derive(rocket_okapi::JsonSchema, rocket::serde::Serialize, rocket::serde::Deserialize)]
 #[serde(crate = "rocket::serde")]
 #[derive(Debug, FromQueryResult)]
 pub struct StockInAndItem {
-    pub number: i32,
-    pub item_id: u32,
+    pub number: i64,
+    pub item_id: i32,
     pub name: String,
     pub specification: Option<String>,
     pub unit: Option<String>,
@@ -20,14 +30,29 @@ pub struct StockInAndItem {
 
 pub async fn get_stock_in_and_items(
     db: &DatabaseConnection,
-    from_date: String,
-    to_date: String,
+    from_date: chrono::NaiveDate,
+    to_date: chrono::NaiveDate,
 ) -> Result<Vec<StockInAndItem>, DbErr> {
-    Item::find()
-        .from_raw_sql(Statement::from_string(
-            DbBackend::MySql,
-            format!("SELECT CAST(SUM(`batch`.`number`) as INTEGER) AS `number`, `batch`.`item_id`, `item`.`name`,`item`.`specification`,`item`.`unit`,`item`.`manufacturer`,`item`.`price` FROM `item` INNER JOIN `batch` ON `batch`.`item_id`=`item`.`id` WHERE `batch`.`date`>= \"{}\" AND `batch`.`date`<= \"{}\" GROUP BY `batch`.`item_id` ORDER BY `item`.`price` DESC", from_date, to_date),
-        )).into_model::<StockInAndItem>()
+    Batch::find()
+        .select_only()
+        .column_as(
+            Expr::col((Batch, batch::Column::Number))
+                .sum()
+                .cast_as(bigint_type(db.get_database_backend())),
+            "number",
+        )
+        .column_as(item::Column::Id, "item_id")
+        .column(item::Column::Name)
+        .column(item::Column::Specification)
+        .column(item::Column::Unit)
+        .column(item::Column::Manufacturer)
+        .column(item::Column::Price)
+        .join(JoinType::InnerJoin, batch::Relation::Item.def())
+        .filter(batch::Column::Date.gte(from_date))
+        .filter(batch::Column::Date.lte(to_date))
+        .group_by(item::Column::Id)
+        .order_by_desc(item::Column::Price)
+        .into_model::<StockInAndItem>()
         .all(db)
         .await
 }
@@ -36,165 +61,378 @@ pub async fn get_stock_in_and_items(
 #[serde(crate = "rocket::serde")]
 #[derive(Debug, FromQueryResult)]
 pub struct BatchAndItem {
-    pub id: u32,
+    pub id: i32,
     pub date: chrono::NaiveDate,
     pub number: i32,
     pub expiration: chrono::NaiveDate,
     pub vendor: Option<String>,
-    pub disabled: u8,
-    pub item_id: u32,
+    pub disabled: bool,
+    pub item_id: i32,
+    pub remaining: i32,
+    pub version: i32,
 
     pub name: String,
     pub specification: Option<String>,
     pub unit: Option<String>,
     pub manufacturer: String,
     pub price: f32,
+    pub sku: Option<String>,
 }
 
-pub async fn get_batches_and_items(db: &DatabaseConnection) -> Result<Vec<BatchAndItem>, DbErr> {
+pub struct BatchFilter {
+    pub item_id: Option<i32>,
+    pub vendor: Option<String>,
+    /// Received (batch date) range, both ends inclusive.
+    pub received: (Option<chrono::NaiveDate>, Option<chrono::NaiveDate>),
+    /// Expiration range, both ends inclusive.
+    pub expires: (Option<chrono::NaiveDate>, Option<chrono::NaiveDate>),
+    pub include_disabled: bool,
+    /// Case-insensitive substring of the item name.
+    pub search: Option<String>,
+    pub sort: batch::Column,
+    pub order: Order,
+}
+
+fn filter_batches_and_items(filter: BatchFilter) -> Selector<SelectModel<BatchAndItem>> {
+    let mut condition = Condition::all();
+    if let Some(item_id) = filter.item_id {
+        condition = condition.add(batch::Column::ItemId.eq(item_id));
+    }
+    if let Some(vendor) = filter.vendor {
+        condition = condition.add(batch::Column::Vendor.eq(vendor));
+    }
+    if let Some(from) = filter.received.0 {
+        condition = condition.add(batch::Column::Date.gte(from));
+    }
+    if let Some(to) = filter.received.1 {
+        condition = condition.add(batch::Column::Date.lte(to));
+    }
+    if let Some(from) = filter.expires.0 {
+        condition = condition.add(batch::Column::Expiration.gte(from));
+    }
+    if let Some(to) = filter.expires.1 {
+        condition = condition.add(batch::Column::Expiration.lte(to));
+    }
+    if !filter.include_disabled {
+        condition = condition.add(batch::Column::Disabled.eq(false));
+    }
+    if let Some(search) = filter.search.as_deref().filter(|s| !s.is_empty()) {
+        condition = condition.add(contains_ignore_case((Item, item::Column::Name), search));
+    }
+
     Batch::find()
-        .from_raw_sql(Statement::from_string(
-            DbBackend::MySql,
-            r#"SELECT `batch`.*, `item`.`name`,`item`.`specification`,`item`.`unit`,`item`.`manufacturer`,`item`.`price` FROM `item` INNER JOIN `batch` ON `batch`.`item_id`=`item`.`id` ORDER BY `batch`.`date` DESC, `batch`.`id` DESC"#
-                .to_string(),
-        )).into_model::<BatchAndItem>()
+        .column(item::Column::Name)
+        .column(item::Column::Specification)
+        .column(item::Column::Unit)
+        .column(item::Column::Manufacturer)
+        .column(item::Column::Price)
+        .column(item::Column::Sku)
+        .join(JoinType::InnerJoin, batch::Relation::Item.def())
+        .filter(condition)
+        .order_by(filter.sort, filter.order.clone())
+        .order_by(batch::Column::Id, filter.order)
+        .into_model::<BatchAndItem>()
+}
+
+/// Returns one page (1-based) of the matching batches and the total number of matches.
+pub async fn get_batches_and_items(
+    db: &DatabaseConnection,
+    filter: BatchFilter,
+    page: usize,
+    per_page: usize,
+) -> Result<(Vec<BatchAndItem>, usize), DbErr> {
+    let paginator = filter_batches_and_items(filter).paginate(db, per_page);
+    let total = paginator.num_items().await?;
+    let batches = paginator.fetch_page(page - 1).await?;
+
+    Ok((batches, total))
+}
+
+/// Returns every matching batch, unpaginated.
+pub async fn get_all_batches_and_items(
+    db: &DatabaseConnection,
+    filter: BatchFilter,
+) -> Result<Vec<BatchAndItem>, DbErr> {
+    filter_batches_and_items(filter).all(db).await
+}
+
+/// An item's enabled batches that still hold stock, earliest expiry first:
+/// the order stock is drawn from them in.
+pub async fn get_stocked_batches<T: ConnectionTrait>(
+    db: &T,
+    item_id: i32,
+) -> Result<Vec<batch::Model>, DbErr> {
+    Batch::find()
+        .filter(batch::Column::ItemId.eq(item_id))
+        .filter(batch::Column::Disabled.eq(false))
+        .filter(batch::Column::Remaining.gt(0))
+        .order_by_asc(batch::Column::Expiration)
+        .order_by_asc(batch::Column::Id)
         .all(db)
         .await
 }
 
-pub async fn get_max_id<T: ConnectionTrait>(db: &T) -> Result<u32, DbErr> {
-    Ok(Batch::find()
-        .column(batch::Column::Id)
-        .all(db)
+/// The expiration of an item without stock, later than any real one.
+pub fn no_stock_expiration() -> chrono::NaiveDate {
+    chrono::NaiveDate::from_ymd(2099, 12, 31)
+}
+
+/// An item's expiration: the earliest of its batches that still hold stock.
+pub async fn get_stock_expiration<T: ConnectionTrait>(
+    db: &T,
+    item_id: i32,
+) -> Result<chrono::NaiveDate, DbErr> {
+    Ok(get_stocked_batches(db, item_id)
         .await?
-        .into_iter()
-        .fold(0, |max, x| x.id.max(max)) as u32)
+        .first()
+        .map_or_else(no_stock_expiration, |batch| batch.expiration))
 }
 
+/// Records a received batch. Its units count towards the item's stock, and
+/// its expiration towards the item's, unless it is received disabled.
 pub async fn create_batch_transaction(
     transaction: &DatabaseTransaction,
     batch: batch::Model,
-) -> Result<(), DbErr> {
-    let next_id = get_max_id(transaction).await? + 1;
+    context: &AuditContext,
+) -> Result<(), BatchError> {
+    if batch.number <= 0 {
+        return Err(BatchError::InvalidNumber(batch.number));
+    }
 
-    Batch::insert(batch::ActiveModel {
-        id: ActiveValue::Set(batch.id.max(next_id)),
+    let batch_id = Batch::insert(batch::ActiveModel {
+        id: ActiveValue::NotSet,
         date: ActiveValue::Set(batch.date),
         number: ActiveValue::Set(batch.number),
         expiration: ActiveValue::Set(batch.expiration),
-        vendor: ActiveValue::Set(batch.vendor),
+        vendor: ActiveValue::Set(batch.vendor.clone()),
         disabled: ActiveValue::Set(batch.disabled),
         item_id: ActiveValue::Set(batch.item_id),
+        remaining: ActiveValue::Set(batch.number),
+        version: ActiveValue::Set(1),
     })
     .exec(transaction)
-    .await?;
+    .await?
+    .last_insert_id;
 
-    let item = match Item::find_by_id(batch.item_id).one(transaction).await? {
-        Some(item) => item,
-        None => return Err(DbErr::RecordNotFound(String::from("Item not found."))),
-    };
+    if !batch.disabled {
+        let item = match Item::find_by_id(batch.item_id).one(transaction).await? {
+            Some(item) => item,
+            None => return Err(DbErr::RecordNotFound(String::from("Item not found.")).into()),
+        };
+        let number = change_stock(item.id, item.number, batch.number)?;
+        let expiration = get_stock_expiration(transaction, item.id).await?;
+        let mut active_model: item::ActiveModel = item.into();
+        active_model.number = ActiveValue::Set(number);
+        active_model.expiration = ActiveValue::Set(expiration);
+        save_item_transaction::<BatchError>(transaction, active_model).await?;
+    }
 
-    let active_model = item::ActiveModel {
-        id: ActiveValue::Unchanged(item.id),
-        name: ActiveValue::Unchanged(item.name),
-        specification: ActiveValue::Unchanged(item.specification),
-        unit: ActiveValue::Unchanged(item.unit),
-        manufacturer: ActiveValue::Unchanged(item.manufacturer),
-        number: ActiveValue::Set(item.number + batch.number),
-        price: ActiveValue::Unchanged(item.price),
-        expiration: ActiveValue::Set(item.expiration.min(batch.expiration)),
+    let batch = batch::Model {
+        id: batch_id,
+        remaining: batch.number,
+        version: 1,
+        ..batch
     };
-    active_model.update(transaction).await?;
+    audit::record_transaction(
+        transaction,
+        context,
+        AuditEntity::Batch,
+        batch.id,
+        AuditAction::Create,
+        None,
+        Some(&batch),
+    )
+    .await?;
 
     Ok(())
 }
 
-pub async fn create_batch(db: &DatabaseConnection, batch: batch::Model) -> Result<(), DbErr> {
+pub async fn create_batch(
+    db: &DatabaseConnection,
+    batch: batch::Model,
+    context: &AuditContext,
+) -> Result<(), BatchError> {
     let transaction = db.begin().await?;
+    create_batch_transaction(&transaction, batch, context).await?;
+    transaction.commit().await?;
 
-    let next_id = get_max_id(&transaction).await? + 1;
+    Ok(())
+}
 
-    Batch::insert(batch::ActiveModel {
-        id: ActiveValue::Set(batch.id.max(next_id)),
-        date: ActiveValue::Set(batch.date),
-        number: ActiveValue::Set(batch.number),
-        expiration: ActiveValue::Set(batch.expiration),
-        vendor: ActiveValue::Set(batch.vendor),
-        disabled: ActiveValue::Set(batch.disabled),
-        item_id: ActiveValue::Set(batch.item_id),
-    })
-    .exec(&transaction)
-    .await?;
+/// Fields of a batch that can be edited after it was received.
+#[derive(rocket_okapi::JsonSchema, rocket::serde::Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BatchPatch {
+    pub date: Option<chrono::NaiveDate>,
+    pub number: Option<i32>,
+    pub expiration: Option<chrono::NaiveDate>,
+    pub vendor: Option<String>,
+    pub disabled: Option<bool>,
+    /// Required when disabling the batch; its remaining stock is written off.
+    pub reason: Option<WriteOffReason>,
+}
 
-    let item = match Item::find_by_id(batch.item_id).one(&transaction).await? {
-        Some(item) => item,
-        None => return Err(DbErr::RecordNotFound(String::from("Item not found."))),
-    };
+pub enum BatchError {
+    Db(DbErr),
+    InvalidNumber(i32),
+    /// The new number is less than what stock-outs already drew from the batch.
+    BelowDrawn {
+        drawn: i32,
+        requested: i32,
+    },
+    /// Disabling a batch without saying why.
+    MissingReason,
+    Version(VersionConflict),
+    Overflow(StockOverflow),
+}
 
-    let active_model = item::ActiveModel {
-        id: ActiveValue::Unchanged(item.id),
-        name: ActiveValue::Unchanged(item.name),
-        specification: ActiveValue::Unchanged(item.specification),
-        unit: ActiveValue::Unchanged(item.unit),
-        manufacturer: ActiveValue::Unchanged(item.manufacturer),
-        number: ActiveValue::Set(item.number + batch.number),
-        price: ActiveValue::Unchanged(item.price),
-        expiration: ActiveValue::Set(item.expiration.min(batch.expiration)),
-    };
-    active_model.update(&transaction).await?;
+impl From<DbErr> for BatchError {
+    fn from(err: DbErr) -> Self {
+        BatchError::Db(err)
+    }
+}
+
+impl From<VersionConflict> for BatchError {
+    fn from(conflict: VersionConflict) -> Self {
+        BatchError::Version(conflict)
+    }
+}
+
+impl From<StockOverflow> for BatchError {
+    fn from(overflow: StockOverflow) -> Self {
+        BatchError::Overflow(overflow)
+    }
+}
 
-    transaction.commit().await
+pub async fn get_batch(db: &DatabaseConnection, id: i32) -> Result<batch::Model, DbErr> {
+    match Batch::find_by_id(id).one(db).await? {
+        Some(batch) => Ok(batch),
+        None => Err(DbErr::RecordNotFound(String::from("Batch not found!"))),
+    }
 }
 
-pub async fn disable_batch(db: &DatabaseConnection, id: u32) -> Result<(), DbErr> {
+/// Writes back a batch read earlier in the transaction and bumps its
+/// version, unless another transaction changed the batch in between.
+pub async fn save_batch_transaction<E: From<DbErr> + From<VersionConflict>>(
+    transaction: &DatabaseTransaction,
+    mut batch: batch::ActiveModel,
+) -> Result<batch::Model, E> {
+    let id = batch.id.clone().unwrap();
+    let version = batch.version.clone().unwrap();
+    batch.version = ActiveValue::Set(version + 1);
+
+    match Batch::update(batch)
+        .filter(batch::Column::Version.eq(version))
+        .exec(transaction)
+        .await
+    {
+        Ok(batch) => Ok(batch),
+        Err(DbErr::RecordNotFound(_)) => Err(VersionConflict::Concurrent {
+            entity: "Batch",
+            id,
+        }
+        .into()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Applies a partial edit to a batch, keeping the item's stock and
+/// expiration in step with it.
+///
+/// Disabling a batch writes off whatever is left of it, so that stock no
+/// longer counts towards the item. Re-enabling does not bring written-off
+/// stock back, but a batch received disabled then counts with all it holds.
+///
+/// The edit is refused if the batch is no longer at the `expected` version,
+/// when one is given.
+pub async fn modify_batch(
+    db: &DatabaseConnection,
+    id: i32,
+    patch: BatchPatch,
+    expected: Option<i32>,
+    context: &AuditContext,
+) -> Result<batch::Model, BatchError> {
     let transaction = db.begin().await?;
 
-    let batch = Batch::find_by_id(id).one(&transaction).await?;
-    let batch = match batch {
+    let before = match Batch::find_by_id(id).one(&transaction).await? {
         Some(batch) => batch,
-        None => return Err(DbErr::RecordNotFound(String::from("Batch not found!"))),
-    };
-
-    let active_model = batch::ActiveModel {
-        id: ActiveValue::Unchanged(batch.id),
-        date: ActiveValue::Unchanged(batch.date),
-        number: ActiveValue::Unchanged(batch.number),
-        expiration: ActiveValue::Unchanged(batch.expiration),
-        vendor: ActiveValue::Unchanged(batch.vendor),
-        disabled: ActiveValue::Set(1),
-        item_id: ActiveValue::Unchanged(batch.item_id),
+        None => return Err(DbErr::RecordNotFound(String::from("Batch not found!")).into()),
     };
-    active_model.update(&transaction).await?;
+    check_version("Batch", id, before.version, expected)?;
+    let batch = before.clone();
 
-    let min_expiration = Batch::find()
-        .filter(batch::Column::ItemId.eq(batch.item_id))
-        .filter(batch::Column::Disabled.ne(1))
-        .all(&transaction)
-        .await?
-        .into_iter()
-        .fold(chrono::NaiveDate::from_ymd(2099, 12, 31), |min, item| {
-            if item.expiration < min {
-                item.expiration
-            } else {
-                min
-            }
+    let number = patch.number.unwrap_or(batch.number);
+    if number <= 0 {
+        return Err(BatchError::InvalidNumber(number));
+    }
+    // What the batch adds to the item's stock, before and after the edit.
+    let held_before = if batch.disabled { 0 } else { batch.remaining };
+    let drawn = batch.number - batch.remaining;
+    if number < drawn {
+        return Err(BatchError::BelowDrawn {
+            drawn,
+            requested: number,
         });
+    }
+    let delta = number - batch.number;
+
+    let remaining = batch.remaining + delta;
+    let mut active_model: batch::ActiveModel = batch.into();
+    if let Some(date) = patch.date {
+        active_model.date = ActiveValue::Set(date);
+    }
+    active_model.number = ActiveValue::Set(number);
+    if let Some(expiration) = patch.expiration {
+        active_model.expiration = ActiveValue::Set(expiration);
+    }
+    if let Some(vendor) = patch.vendor {
+        active_model.vendor = ActiveValue::Set(Some(vendor));
+    }
+    if let Some(disabled) = patch.disabled {
+        active_model.disabled = ActiveValue::Set(disabled);
+    }
+    active_model.remaining = ActiveValue::Set(remaining);
+    let mut batch = save_batch_transaction::<BatchError>(&transaction, active_model).await?;
+
+    if patch.disabled == Some(true) && !before.disabled {
+        let reason = match patch.reason {
+            Some(reason) => reason,
+            None => return Err(BatchError::MissingReason),
+        };
+        if batch.remaining > 0 {
+            write_off_transaction(&transaction, &batch, reason).await?;
+
+            let mut active_model: batch::ActiveModel = batch.into();
+            active_model.remaining = ActiveValue::Set(0);
+            batch = save_batch_transaction::<BatchError>(&transaction, active_model).await?;
+        }
+    }
+
+    let held_after = if batch.disabled { 0 } else { batch.remaining };
 
     let item = match Item::find_by_id(batch.item_id).one(&transaction).await? {
         Some(item) => item,
-        None => return Err(DbErr::RecordNotFound(String::from("Item not found."))),
+        None => return Err(DbErr::RecordNotFound(String::from("Item not found.")).into()),
     };
-    let active_model = item::ActiveModel {
-        id: ActiveValue::Unchanged(item.id),
-        name: ActiveValue::Unchanged(item.name),
-        specification: ActiveValue::Unchanged(item.specification),
-        unit: ActiveValue::Unchanged(item.unit),
-        manufacturer: ActiveValue::Unchanged(item.manufacturer),
-        number: ActiveValue::Unchanged(item.number),
-        price: ActiveValue::Unchanged(item.price),
-        expiration: ActiveValue::Set(min_expiration),
-    };
-    active_model.update(&transaction).await?;
+    let number = change_stock(item.id, item.number, held_after - held_before)?;
+    let expiration = get_stock_expiration(&transaction, item.id).await?;
+    let mut active_model: item::ActiveModel = item.into();
+    active_model.number = ActiveValue::Set(number);
+    active_model.expiration = ActiveValue::Set(expiration);
+    save_item_transaction::<BatchError>(&transaction, active_model).await?;
+
+    audit::record_transaction(
+        &transaction,
+        context,
+        AuditEntity::Batch,
+        batch.id,
+        AuditAction::Update,
+        Some(&before),
+        Some(&batch),
+    )
+    .await?;
+
+    transaction.commit().await?;
 
-    transaction.commit().await
+    Ok(batch)
 }
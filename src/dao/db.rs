@@ -1,8 +1,13 @@
-use sea_orm::{ConnectionTrait, Database, DatabaseConnection, DbBackend, DbErr, Statement};
+use sea_orm::{
+    sea_query::{Alias, Expr, Func, IntoColumnRef, LikeExpr, SimpleExpr},
+    ConnectionTrait, Database, DatabaseConnection, DbBackend, DbErr, Statement,
+};
 
 pub async fn setup_db() -> Result<DatabaseConnection, DbErr> {
     // Environment variable DATABASE_URL
     // mysql://<user>:<password>@<host>:<port>
+    // postgres://<user>:<password>@<host>:<port>
+    // sqlite://<path>?mode=rwc
     let database_url = match std::env::var("DATABASE_URL") {
         Ok(url) => url,
         Err(_) => {
@@ -26,6 +31,96 @@ pub async fn setup_db() -> Result<DatabaseConnection, DbErr> {
             let url = format!("{}/{}", &database_url, db_name);
             Ok(Database::connect(&url).await?)
         }
-        _ => Err(DbErr::Custom("Unsupported database detected.".to_string())),
+        DbBackend::Postgres => {
+            // PostgreSQL has no `CREATE DATABASE IF NOT EXISTS`.
+            let exists = db
+                .query_one(Statement::from_sql_and_values(
+                    db.get_database_backend(),
+                    "SELECT 1 FROM pg_database WHERE datname = $1",
+                    vec![db_name.into()],
+                ))
+                .await?
+                .is_some();
+            if !exists {
+                db.execute(Statement::from_string(
+                    db.get_database_backend(),
+                    format!("CREATE DATABASE \"{}\";", db_name),
+                ))
+                .await?;
+            }
+
+            let url = format!("{}/{}", &database_url, db_name);
+            Ok(Database::connect(&url).await?)
+        }
+        // A SQLite database is the file named by the URL itself.
+        DbBackend::Sqlite => Ok(db),
+    }
+}
+
+/// Type that `SUM()` results are cast to, so every backend yields a 64-bit integer.
+pub fn bigint_type(backend: DbBackend) -> Alias {
+    match backend {
+        DbBackend::MySql => Alias::new("SIGNED"),
+        DbBackend::Postgres | DbBackend::Sqlite => Alias::new("BIGINT"),
+    }
+}
+
+/// Case-insensitive substring match, with `LIKE` wildcards in `search` matched literally.
+pub fn contains_ignore_case<C: IntoColumnRef>(column: C, search: &str) -> SimpleExpr {
+    let escaped = search
+        .to_lowercase()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    Expr::expr(Func::lower(Expr::col(column)))
+        .like(LikeExpr::str(&format!("%{}%", escaped)).escape('\\'))
+}
+
+/// A versioned record that is not at the version a change expects.
+pub enum VersionConflict {
+    /// The version the client sent with `If-Match` is not the record's.
+    Precondition {
+        entity: &'static str,
+        id: i32,
+        current: i32,
+    },
+    /// Another transaction changed the record between its read and write.
+    Concurrent { entity: &'static str, id: i32 },
+}
+
+/// Checks a record's version against the one a client expects, if any.
+pub fn check_version(
+    entity: &'static str,
+    id: i32,
+    current: i32,
+    expected: Option<i32>,
+) -> Result<(), VersionConflict> {
+    match expected {
+        Some(expected) if expected != current => Err(VersionConflict::Precondition {
+            entity,
+            id,
+            current,
+        }),
+        _ => Ok(()),
     }
 }
+
+/// Stock of an item that would no longer fit the columns it is kept in.
+pub struct StockOverflow {
+    pub item_id: i32,
+}
+
+/// Changes a stock count of an item by `change`, refusing to overflow.
+pub fn change_stock(item_id: i32, stock: i32, change: i32) -> Result<i32, StockOverflow> {
+    stock.checked_add(change).ok_or(StockOverflow { item_id })
+}
+
+/// Adds up stock counts of an item, refusing to overflow.
+pub fn total_stock(
+    item_id: i32,
+    stocks: impl IntoIterator<Item = i32>,
+) -> Result<i32, StockOverflow> {
+    stocks
+        .into_iter()
+        .try_fold(0, |total, stock| change_stock(item_id, total, stock))
+}
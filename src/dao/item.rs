@@ -1,31 +1,121 @@
-use crate::models::{batch, item, prelude::*, stock_out};
+use super::audit::{self, AuditContext};
+use super::batch::no_stock_expiration;
+use super::db::{check_version, contains_ignore_case, VersionConflict};
+use crate::models::{
+    batch, item,
+    prelude::*,
+    sea_orm_active_enums::{AuditAction, AuditEntity},
+    stock_adjustment, stock_adjustment_batch, stock_out, stock_out_batch, stock_out_override,
+    write_off,
+};
 use sea_orm::{
-    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection,
-    DatabaseTransaction, DbErr, EntityTrait, InsertResult, QueryFilter, QuerySelect,
-    TransactionTrait,
+    ActiveValue, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, DatabaseTransaction,
+    DbErr, EntityTrait, Order, PaginatorTrait, QueryFilter, QueryOrder, Select, TransactionTrait,
 };
 
+/// Every item, archived ones included.
 pub async fn get_items<T: ConnectionTrait>(db: &T) -> Result<Vec<item::Model>, DbErr> {
     Item::find().into_model().all(db).await
 }
 
-pub async fn get_max_id<T: ConnectionTrait>(db: &T) -> Result<u32, DbErr> {
-    Ok(Item::find()
-        .column(item::Column::Id)
-        .all(db)
-        .await?
-        .into_iter()
-        .fold(0, |max, x| x.id.max(max)) as u32)
+pub async fn get_item(db: &DatabaseConnection, id: i32) -> Result<item::Model, DbErr> {
+    match Item::find_by_id(id).one(db).await? {
+        Some(item) => Ok(item),
+        None => Err(DbErr::RecordNotFound(String::from("Item not found."))),
+    }
+}
+
+pub struct ItemFilter {
+    /// Case-insensitive substring of the name, manufacturer, specification or SKU.
+    pub search: Option<String>,
+    /// Only items with at most this many in stock.
+    pub low_stock: Option<i32>,
+    /// Only items whose earliest expiration is before this date.
+    pub expiring_before: Option<chrono::NaiveDate>,
+    /// List the archived items instead of the others.
+    pub archived: bool,
+    pub sort: item::Column,
+    pub order: Order,
+}
+
+fn filter_items(filter: ItemFilter) -> Select<Item> {
+    let mut condition = Condition::all().add(item::Column::Archived.eq(filter.archived));
+    if let Some(search) = filter.search.as_deref().filter(|s| !s.is_empty()) {
+        condition = condition.add(
+            [
+                item::Column::Name,
+                item::Column::Manufacturer,
+                item::Column::Specification,
+                item::Column::Sku,
+            ]
+            .into_iter()
+            .fold(Condition::any(), |any, column| {
+                any.add(contains_ignore_case(column, search))
+            }),
+        );
+    }
+    if let Some(low_stock) = filter.low_stock {
+        condition = condition.add(item::Column::Number.lte(low_stock));
+    }
+    if let Some(expiring_before) = filter.expiring_before {
+        condition = condition.add(item::Column::Expiration.lt(expiring_before));
+    }
+
+    Item::find()
+        .filter(condition)
+        .order_by(filter.sort, filter.order)
+        .order_by_asc(item::Column::Id)
+}
+
+/// Returns one page (1-based) of the matching items and the total number of matches.
+pub async fn search_items(
+    db: &DatabaseConnection,
+    filter: ItemFilter,
+    page: usize,
+    per_page: usize,
+) -> Result<(Vec<item::Model>, usize), DbErr> {
+    let paginator = filter_items(filter).paginate(db, per_page);
+    let total = paginator.num_items().await?;
+    let items = paginator.fetch_page(page - 1).await?;
+
+    Ok((items, total))
 }
 
+/// Returns every matching item, unpaginated.
+pub async fn search_all_items(
+    db: &DatabaseConnection,
+    filter: ItemFilter,
+) -> Result<Vec<item::Model>, DbErr> {
+    filter_items(filter).all(db).await
+}
+
+/// A blank SKU is no SKU, so that items without one do not collide.
+fn normalize_sku(sku: Option<String>) -> Option<String> {
+    sku.map(|sku| sku.trim().to_string())
+        .filter(|sku| !sku.is_empty())
+}
+
+/// Records a new item. It starts without stock, which only batches bring.
 pub async fn insert_item_transaction(
     transaction: &DatabaseTransaction,
-    item: item::Model,
-) -> Result<InsertResult<item::ActiveModel>, DbErr> {
-    let next_id = get_max_id(transaction).await? + 1;
-
-    Item::insert(item::ActiveModel {
-        id: ActiveValue::Set(item.id.max(next_id)),
+    details: ItemDetails,
+    context: &AuditContext,
+) -> Result<item::Model, DbErr> {
+    let item = item::Model {
+        id: 0,
+        name: details.name,
+        specification: details.specification,
+        unit: details.unit,
+        manufacturer: details.manufacturer,
+        number: 0,
+        price: details.price,
+        expiration: no_stock_expiration(),
+        sku: normalize_sku(details.sku),
+        archived: false,
+        version: 1,
+    };
+    let result = Item::insert(item::ActiveModel {
+        id: ActiveValue::NotSet,
         name: ActiveValue::Set(item.name.clone()),
         specification: ActiveValue::Set(item.specification.clone()),
         unit: ActiveValue::Set(item.unit.clone()),
@@ -33,49 +123,259 @@ pub async fn insert_item_transaction(
         number: ActiveValue::Set(item.number),
         price: ActiveValue::Set(item.price),
         expiration: ActiveValue::Set(item.expiration),
+        sku: ActiveValue::Set(item.sku.clone()),
+        archived: ActiveValue::Set(item.archived),
+        version: ActiveValue::Set(item.version),
     })
     .exec(transaction)
-    .await
+    .await?;
+
+    let item = item::Model {
+        id: result.last_insert_id,
+        ..item
+    };
+    audit::record_transaction(
+        transaction,
+        context,
+        AuditEntity::Item,
+        item.id,
+        AuditAction::Create,
+        None,
+        Some(&item),
+    )
+    .await?;
+
+    Ok(item)
 }
 
 pub async fn insert_item(
     db: &DatabaseConnection,
-    item: item::Model,
-) -> Result<InsertResult<item::ActiveModel>, DbErr> {
-    let next_id = get_max_id(db as &DatabaseConnection).await? + 1;
+    details: ItemDetails,
+    context: &AuditContext,
+) -> Result<item::Model, DbErr> {
+    let transaction = db.begin().await?;
+    let item = insert_item_transaction(&transaction, details, context).await?;
+    transaction.commit().await?;
 
-    Item::insert(item::ActiveModel {
-        id: ActiveValue::Set(item.id.max(next_id)),
-        name: ActiveValue::Set(item.name.clone()),
-        specification: ActiveValue::Set(item.specification.clone()),
-        unit: ActiveValue::Set(item.unit.clone()),
-        manufacturer: ActiveValue::Set(item.manufacturer.clone()),
-        number: ActiveValue::Set(item.number),
-        price: ActiveValue::Set(item.price),
-        expiration: ActiveValue::Set(item.expiration),
-    })
-    .exec(db)
-    .await
+    Ok(item)
 }
 
-pub async fn modify_item(db: &DatabaseConnection, item: item::Model) -> Result<item::Model, DbErr> {
-    let item = item::ActiveModel {
-        id: ActiveValue::Set(item.id),
-        name: ActiveValue::Set(item.name.clone()),
-        specification: ActiveValue::Set(item.specification.clone()),
-        unit: ActiveValue::Set(item.unit.clone()),
-        manufacturer: ActiveValue::Set(item.manufacturer.clone()),
-        number: ActiveValue::Set(item.number),
-        price: ActiveValue::Set(item.price),
-        expiration: ActiveValue::Set(item.expiration),
+pub enum ItemError {
+    Db(DbErr),
+    /// Deleting an item that has history without purging it.
+    HasHistory {
+        id: i32,
+        batches: usize,
+        stock_outs: usize,
+        adjustments: usize,
+    },
+    Version(VersionConflict),
+}
+
+impl From<DbErr> for ItemError {
+    fn from(err: DbErr) -> Self {
+        ItemError::Db(err)
+    }
+}
+
+impl From<VersionConflict> for ItemError {
+    fn from(conflict: VersionConflict) -> Self {
+        ItemError::Version(conflict)
+    }
+}
+
+/// Writes back an item read earlier in the transaction and bumps its
+/// version, unless another transaction changed the item in between.
+pub async fn save_item_transaction<E: From<DbErr> + From<VersionConflict>>(
+    transaction: &DatabaseTransaction,
+    mut item: item::ActiveModel,
+) -> Result<item::Model, E> {
+    let id = item.id.clone().unwrap();
+    let version = item.version.clone().unwrap();
+    item.version = ActiveValue::Set(version + 1);
+
+    match Item::update(item)
+        .filter(item::Column::Version.eq(version))
+        .exec(transaction)
+        .await
+    {
+        Ok(item) => Ok(item),
+        Err(DbErr::RecordNotFound(_)) => {
+            Err(VersionConflict::Concurrent { entity: "Item", id }.into())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// The fields of an item that are given when creating or editing it. Its
+/// stock and expiration follow from its batches, stock-outs and stock
+/// adjustments instead.
+#[derive(rocket_okapi::JsonSchema, rocket::serde::Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ItemDetails {
+    pub name: String,
+    pub specification: Option<String>,
+    pub unit: Option<String>,
+    pub manufacturer: String,
+    pub price: f32,
+    #[serde(default)]
+    pub sku: Option<String>,
+}
+
+/// Edits an item, provided it is still at the `expected` version if one is
+/// given.
+pub async fn modify_item(
+    db: &DatabaseConnection,
+    id: i32,
+    details: ItemDetails,
+    expected: Option<i32>,
+    context: &AuditContext,
+) -> Result<item::Model, ItemError> {
+    let transaction = db.begin().await?;
+
+    let before = match Item::find_by_id(id).one(&transaction).await? {
+        Some(before) => before,
+        None => return Err(DbErr::RecordNotFound(String::from("Item not found.")).into()),
+    };
+    check_version("Item", id, before.version, expected)?;
+    let mut item: item::ActiveModel = before.clone().into();
+    item.name = ActiveValue::Set(details.name);
+    item.specification = ActiveValue::Set(details.specification);
+    item.unit = ActiveValue::Set(details.unit);
+    item.manufacturer = ActiveValue::Set(details.manufacturer);
+    item.price = ActiveValue::Set(details.price);
+    item.sku = ActiveValue::Set(normalize_sku(details.sku));
+    let item = save_item_transaction::<ItemError>(&transaction, item).await?;
+
+    audit::record_transaction(
+        &transaction,
+        context,
+        AuditEntity::Item,
+        item.id,
+        AuditAction::Update,
+        Some(&before),
+        Some(&item),
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(item)
+}
+
+/// Archives or restores an item. Archiving keeps its batches and stock-outs.
+pub async fn set_item_archived(
+    db: &DatabaseConnection,
+    id: i32,
+    archived: bool,
+    expected: Option<i32>,
+    context: &AuditContext,
+) -> Result<item::Model, ItemError> {
+    let transaction = db.begin().await?;
+
+    let before = match Item::find_by_id(id).one(&transaction).await? {
+        Some(before) => before,
+        None => return Err(DbErr::RecordNotFound(String::from("Item not found.")).into()),
     };
+    check_version("Item", id, before.version, expected)?;
+    if before.archived == archived {
+        return Ok(before);
+    }
+    let mut active_model: item::ActiveModel = before.clone().into();
+    active_model.archived = ActiveValue::Set(archived);
+    let item = save_item_transaction::<ItemError>(&transaction, active_model).await?;
 
-    item.update(db).await
+    audit::record_transaction(
+        &transaction,
+        context,
+        AuditEntity::Item,
+        id,
+        if archived {
+            AuditAction::Archive
+        } else {
+            AuditAction::Restore
+        },
+        Some(&before),
+        Some(&item),
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(item)
 }
 
-pub async fn delete_item(db: &DatabaseConnection, id: u32) -> Result<(), DbErr> {
+/// Deletes an item. One with batches, stock-outs or stock adjustments is
+/// refused unless `purge` is set, which deletes that history along with it.
+pub async fn delete_item(
+    db: &DatabaseConnection,
+    id: i32,
+    purge: bool,
+    expected: Option<i32>,
+    context: &AuditContext,
+) -> Result<(), ItemError> {
     let transaction = db.begin().await?;
 
+    let item = match Item::find_by_id(id).one(&transaction).await? {
+        Some(item) => item,
+        None => return Err(DbErr::RecordNotFound(String::from("Item not found.")).into()),
+    };
+    check_version("Item", id, item.version, expected)?;
+    if !purge {
+        let batches = Batch::find()
+            .filter(batch::Column::ItemId.eq(id))
+            .count(&transaction)
+            .await?;
+        let stock_outs = StockOut::find()
+            .filter(stock_out::Column::ItemId.eq(id))
+            .count(&transaction)
+            .await?;
+        let adjustments = StockAdjustment::find()
+            .filter(stock_adjustment::Column::ItemId.eq(id))
+            .count(&transaction)
+            .await?;
+        if batches > 0 || stock_outs > 0 || adjustments > 0 {
+            return Err(ItemError::HasHistory {
+                id,
+                batches,
+                stock_outs,
+                adjustments,
+            });
+        }
+    }
+
+    let batches = Batch::find()
+        .filter(batch::Column::ItemId.eq(id))
+        .all(&transaction)
+        .await?;
+    let stock_outs = StockOut::find()
+        .filter(stock_out::Column::ItemId.eq(id))
+        .all(&transaction)
+        .await?;
+    let stock_out_ids: Vec<i32> = stock_outs.iter().map(|s| s.id).collect();
+    StockOutOverride::delete_many()
+        .filter(stock_out_override::Column::ItemId.eq(id))
+        .exec(&transaction)
+        .await?;
+    StockOutBatch::delete_many()
+        .filter(stock_out_batch::Column::StockOutId.is_in(stock_out_ids))
+        .exec(&transaction)
+        .await?;
+    let adjustment_ids: Vec<i32> = StockAdjustment::find()
+        .filter(stock_adjustment::Column::ItemId.eq(id))
+        .all(&transaction)
+        .await?
+        .into_iter()
+        .map(|a| a.id)
+        .collect();
+    StockAdjustmentBatch::delete_many()
+        .filter(stock_adjustment_batch::Column::StockAdjustmentId.is_in(adjustment_ids))
+        .exec(&transaction)
+        .await?;
+    WriteOff::delete_many()
+        .filter(write_off::Column::ItemId.eq(id))
+        .exec(&transaction)
+        .await?;
     Batch::delete_many()
         .filter(batch::Column::ItemId.eq(id))
         .exec(&transaction)
@@ -84,7 +384,55 @@ pub async fn delete_item(db: &DatabaseConnection, id: u32) -> Result<(), DbErr>
         .filter(stock_out::Column::ItemId.eq(id))
         .exec(&transaction)
         .await?;
-    Item::delete_by_id(id).exec(&transaction).await?;
+    StockAdjustment::delete_many()
+        .filter(stock_adjustment::Column::ItemId.eq(id))
+        .exec(&transaction)
+        .await?;
+    let result = Item::delete_many()
+        .filter(item::Column::Id.eq(id))
+        .filter(item::Column::Version.eq(item.version))
+        .exec(&transaction)
+        .await?;
+    if result.rows_affected == 0 {
+        return Err(VersionConflict::Concurrent { entity: "Item", id }.into());
+    }
+
+    for batch in &batches {
+        audit::record_transaction(
+            &transaction,
+            context,
+            AuditEntity::Batch,
+            batch.id,
+            AuditAction::Delete,
+            Some(batch),
+            None,
+        )
+        .await?;
+    }
+    for stock_out in &stock_outs {
+        audit::record_transaction(
+            &transaction,
+            context,
+            AuditEntity::StockOut,
+            stock_out.id,
+            AuditAction::Delete,
+            Some(stock_out),
+            None,
+        )
+        .await?;
+    }
+    audit::record_transaction(
+        &transaction,
+        context,
+        AuditEntity::Item,
+        id,
+        AuditAction::Delete,
+        Some(&item),
+        None,
+    )
+    .await?;
+
+    transaction.commit().await?;
 
-    transaction.commit().await
+    Ok(())
 }
@@ -1 +0,0 @@
-SELECT CAST(SUM(`stock_out`.`number`) as INTEGER) AS `number`, `stock_out`.`item_id`, `item`.`name`,`item`.`specification`,`item`.`unit`,`item`.`manufacturer`,`item`.`price` FROM `item` INNER JOIN `stock_out` ON `stock_out`.`item_id`=`item`.`id` WHERE `stock_out`.`date`>= $1 AND `stock_out`.`date`<= $2 GROUP BY `stock_out`.`item_id` ORDER BY `item`.`price` DESC
\ No newline at end of file
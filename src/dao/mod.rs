@@ -1,4 +1,8 @@
+pub mod audit;
 pub mod batch;
 pub mod db;
 pub mod item;
+pub mod stock_adjustment;
 pub mod stock_out;
+pub mod user;
+pub mod write_off;
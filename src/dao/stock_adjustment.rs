@@ -0,0 +1,185 @@
+use super::audit::{self, AuditContext};
+use super::batch::{get_stock_expiration, get_stocked_batches, save_batch_transaction};
+use super::db::{change_stock, check_version, total_stock, StockOverflow, VersionConflict};
+use super::item::save_item_transaction;
+use crate::models::{
+    batch, item,
+    prelude::*,
+    sea_orm_active_enums::{AuditAction, AuditEntity},
+    stock_adjustment, stock_adjustment_batch,
+};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, QueryOrder, TransactionTrait,
+};
+
+pub async fn get_stock_adjustments(
+    db: &DatabaseConnection,
+    item_id: i32,
+) -> Result<Vec<stock_adjustment::Model>, DbErr> {
+    StockAdjustment::find()
+        .filter(stock_adjustment::Column::ItemId.eq(item_id))
+        .order_by_desc(stock_adjustment::Column::CreatedAt)
+        .order_by_desc(stock_adjustment::Column::Id)
+        .all(db)
+        .await
+}
+
+pub async fn get_stock_adjustment_batches(
+    db: &DatabaseConnection,
+    stock_adjustment_id: i32,
+) -> Result<Vec<stock_adjustment_batch::Model>, DbErr> {
+    StockAdjustmentBatch::find()
+        .filter(stock_adjustment_batch::Column::StockAdjustmentId.eq(stock_adjustment_id))
+        .order_by_asc(stock_adjustment_batch::Column::Id)
+        .all(db)
+        .await
+}
+
+/// How an adjustment changes an item's stock.
+pub enum StockChange {
+    /// Adds to the stock, or takes from it when negative.
+    Delta(i32),
+    /// Sets the stock to what a stocktake counted.
+    Counted(i32),
+}
+
+pub enum StockAdjustmentError {
+    Db(DbErr),
+    /// The adjustment would leave less than nothing in stock.
+    InsufficientStock {
+        available: i32,
+        requested: i32,
+    },
+    /// Adding stock without saying which batch it belongs to.
+    MissingBatch,
+    /// The batch is not an enabled batch of the item.
+    InvalidBatch {
+        batch_id: i32,
+        item_id: i32,
+    },
+    Version(VersionConflict),
+    Overflow(StockOverflow),
+}
+
+impl From<DbErr> for StockAdjustmentError {
+    fn from(err: DbErr) -> Self {
+        StockAdjustmentError::Db(err)
+    }
+}
+
+impl From<VersionConflict> for StockAdjustmentError {
+    fn from(conflict: VersionConflict) -> Self {
+        StockAdjustmentError::Version(conflict)
+    }
+}
+
+impl From<StockOverflow> for StockAdjustmentError {
+    fn from(overflow: StockOverflow) -> Self {
+        StockAdjustmentError::Overflow(overflow)
+    }
+}
+
+/// Corrects an item's stock and records by how much and why.
+///
+/// The change is made to the batch `batch_id` if one is given. Otherwise
+/// stock is taken from the item's batches earliest expiry first, as a
+/// stock-out would; stock can only be added to a given batch. How much each
+/// batch changed is recorded with the adjustment.
+///
+/// A stocktake is refused if the item is no longer at the `expected`
+/// version, when one is given, since its stock may have moved since.
+pub async fn adjust_stock(
+    db: &DatabaseConnection,
+    item_id: i32,
+    change: StockChange,
+    batch_id: Option<i32>,
+    reason: String,
+    expected: Option<i32>,
+    context: &AuditContext,
+) -> Result<stock_adjustment::Model, StockAdjustmentError> {
+    let transaction = db.begin().await?;
+
+    let before = match Item::find_by_id(item_id).one(&transaction).await? {
+        Some(item) => item,
+        None => return Err(DbErr::RecordNotFound(String::from("Item not found.")).into()),
+    };
+    check_version("Item", item_id, before.version, expected)?;
+    let delta = match change {
+        StockChange::Delta(delta) => delta,
+        StockChange::Counted(counted) => counted
+            .checked_sub(before.number)
+            .ok_or(StockOverflow { item_id })?,
+    };
+
+    let batches = match batch_id {
+        Some(batch_id) => match Batch::find_by_id(batch_id).one(&transaction).await? {
+            Some(batch) if batch.item_id == item_id && !batch.disabled => vec![batch],
+            _ => return Err(StockAdjustmentError::InvalidBatch { batch_id, item_id }),
+        },
+        None if delta > 0 => return Err(StockAdjustmentError::MissingBatch),
+        None => get_stocked_batches(&transaction, item_id).await?,
+    };
+    let available = total_stock(item_id, batches.iter().map(|batch| batch.remaining))?;
+    if change_stock(item_id, available, delta)? < 0 {
+        return Err(StockAdjustmentError::InsufficientStock {
+            available,
+            requested: -delta,
+        });
+    }
+
+    let adjustment = stock_adjustment::ActiveModel {
+        id: ActiveValue::NotSet,
+        item_id: ActiveValue::Set(item_id),
+        created_at: ActiveValue::Set(chrono::Local::now().naive_local()),
+        operator: ActiveValue::Set(context.actor.clone()),
+        previous: ActiveValue::Set(before.number),
+        delta: ActiveValue::Set(delta),
+        reason: ActiveValue::Set(reason),
+    }
+    .insert(&transaction)
+    .await?;
+
+    let mut left = delta;
+    for batch in batches {
+        let number = left.max(-batch.remaining);
+        if number == 0 {
+            break;
+        }
+        left -= number;
+
+        StockAdjustmentBatch::insert(stock_adjustment_batch::ActiveModel {
+            id: ActiveValue::NotSet,
+            stock_adjustment_id: ActiveValue::Set(adjustment.id),
+            batch_id: ActiveValue::Set(batch.id),
+            number: ActiveValue::Set(number),
+        })
+        .exec(&transaction)
+        .await?;
+
+        let remaining = change_stock(item_id, batch.remaining, number)?;
+        let mut active_model: batch::ActiveModel = batch.into();
+        active_model.remaining = ActiveValue::Set(remaining);
+        save_batch_transaction::<StockAdjustmentError>(&transaction, active_model).await?;
+    }
+
+    let mut active_model: item::ActiveModel = before.clone().into();
+    active_model.number = ActiveValue::Set(change_stock(item_id, before.number, delta)?);
+    active_model.expiration = ActiveValue::Set(get_stock_expiration(&transaction, item_id).await?);
+    let item = save_item_transaction::<StockAdjustmentError>(&transaction, active_model).await?;
+
+    audit::record_transaction(
+        &transaction,
+        context,
+        AuditEntity::Item,
+        item_id,
+        AuditAction::Adjust,
+        Some(&before),
+        Some(&item),
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(adjustment)
+}
@@ -1,16 +1,25 @@
-use crate::models::{item, prelude::*, stock_out};
+use super::audit::{self, AuditContext};
+use super::batch::{get_stock_expiration, get_stocked_batches, save_batch_transaction};
+use super::db::{bigint_type, change_stock, total_stock, StockOverflow, VersionConflict};
+use super::item::save_item_transaction;
+use crate::models::{
+    batch, item,
+    prelude::*,
+    sea_orm_active_enums::{AuditAction, AuditEntity},
+    stock_out, stock_out_batch, stock_out_override,
+};
 use sea_orm::{
-    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, DbBackend,
-    DbErr, EntityTrait, FromQueryResult, QueryFilter, QueryOrder, QuerySelect, Statement,
-    TransactionTrait,
+    sea_query::Expr, ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait,
+    DatabaseConnection, DatabaseTransaction, DbErr, EntityTrait, FromQueryResult, JoinType,
+    QueryFilter, QueryOrder, QuerySelect, RelationTrait, TransactionTrait,
 };
 
 #[derive(rocket_okapi::JsonSchema, rocket::serde::Serialize, rocket::serde::Deserialize)]
 #[serde(crate = "rocket::serde")]
 #[derive(Debug, FromQueryResult)]
 pub struct StockOutAndItem {
-    pub number: i32,
-    pub item_id: u32,
+    pub number: i64,
+    pub item_id: i32,
     pub name: String,
     pub specification: Option<String>,
     pub unit: Option<String>,
@@ -20,76 +29,299 @@ pub struct StockOutAndItem {
 
 pub async fn get_stock_out_and_items(
     db: &DatabaseConnection,
-    from_date: String,
-    to_date: String,
+    from_date: chrono::NaiveDate,
+    to_date: chrono::NaiveDate,
 ) -> Result<Vec<StockOutAndItem>, DbErr> {
-    Item::find()
-        .from_raw_sql(Statement::from_string(
-            DbBackend::MySql,
-            format!("SELECT CAST(SUM(`stock_out`.`number`) as INTEGER) AS `number`, `stock_out`.`item_id`, `item`.`name`,`item`.`specification`,`item`.`unit`,`item`.`manufacturer`,`item`.`price` FROM `item` INNER JOIN `stock_out` ON `stock_out`.`item_id`=`item`.`id` WHERE `stock_out`.`date`>= \"{}\" AND `stock_out`.`date`<= \"{}\" GROUP BY `stock_out`.`item_id` ORDER BY `item`.`price` DESC", from_date, to_date),
-        )).into_model::<StockOutAndItem>()
+    StockOut::find()
+        .select_only()
+        .column_as(
+            Expr::col((StockOut, stock_out::Column::Number))
+                .sum()
+                .cast_as(bigint_type(db.get_database_backend())),
+            "number",
+        )
+        .column_as(item::Column::Id, "item_id")
+        .column(item::Column::Name)
+        .column(item::Column::Specification)
+        .column(item::Column::Unit)
+        .column(item::Column::Manufacturer)
+        .column(item::Column::Price)
+        .join(JoinType::InnerJoin, stock_out::Relation::Item.def())
+        .filter(stock_out::Column::ReversedAt.is_null())
+        .filter(stock_out::Column::Date.gte(from_date))
+        .filter(stock_out::Column::Date.lte(to_date))
+        .group_by(item::Column::Id)
+        .order_by_desc(item::Column::Price)
+        .into_model::<StockOutAndItem>()
         .all(db)
         .await
 }
 
 pub async fn get_stock_out_by_item_id<T: ConnectionTrait>(
     db: &T,
-    id: u32,
+    id: i32,
 ) -> Result<Vec<stock_out::Model>, DbErr> {
     StockOut::find()
         .filter(stock_out::Column::ItemId.eq(id))
         .order_by_desc(stock_out::Column::Date)
+        .order_by_desc(stock_out::Column::CreatedAt)
+        .order_by_desc(stock_out::Column::Id)
         .all(db)
         .await
 }
 
-pub async fn get_max_id<T: ConnectionTrait>(db: &T) -> Result<u32, DbErr> {
-    Ok(StockOut::find()
-        .column(stock_out::Column::Id)
+#[derive(rocket_okapi::JsonSchema, rocket::serde::Serialize, rocket::serde::Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[derive(Debug, FromQueryResult)]
+pub struct DailyStockOut {
+    pub date: chrono::NaiveDate,
+    pub number: i64,
+}
+
+/// Totals an item's stock-outs per day, leaving out reversed ones.
+pub async fn get_daily_stock_out_by_item_id(
+    db: &DatabaseConnection,
+    id: i32,
+) -> Result<Vec<DailyStockOut>, DbErr> {
+    StockOut::find()
+        .select_only()
+        .column(stock_out::Column::Date)
+        .column_as(
+            Expr::col(stock_out::Column::Number)
+                .sum()
+                .cast_as(bigint_type(db.get_database_backend())),
+            "number",
+        )
+        .filter(stock_out::Column::ItemId.eq(id))
+        .filter(stock_out::Column::ReversedAt.is_null())
+        .group_by(stock_out::Column::Date)
+        .order_by_desc(stock_out::Column::Date)
+        .into_model::<DailyStockOut>()
         .all(db)
-        .await?
-        .into_iter()
-        .fold(0, |max, x| x.id.max(max)) as u32)
+        .await
+}
+
+pub async fn get_stock_out_batches<T: ConnectionTrait>(
+    db: &T,
+    stock_out_id: i32,
+) -> Result<Vec<stock_out_batch::Model>, DbErr> {
+    StockOutBatch::find()
+        .filter(stock_out_batch::Column::StockOutId.eq(stock_out_id))
+        .order_by_asc(stock_out_batch::Column::Id)
+        .all(db)
+        .await
+}
+
+/// Draws `number` units from `batches`, in their order, and records how
+/// much was taken from each batch. Returns the part that the batches had
+/// too little left to cover.
+async fn allocate_stock_out(
+    transaction: &DatabaseTransaction,
+    stock_out_id: i32,
+    batches: Vec<batch::Model>,
+    number: i32,
+) -> Result<i32, StockOutError> {
+    let mut left = number;
+    for batch in batches {
+        if left <= 0 {
+            break;
+        }
+        let taken = left.min(batch.remaining);
+        left -= taken;
+
+        StockOutBatch::insert(stock_out_batch::ActiveModel {
+            id: ActiveValue::NotSet,
+            stock_out_id: ActiveValue::Set(stock_out_id),
+            batch_id: ActiveValue::Set(batch.id),
+            number: ActiveValue::Set(taken),
+        })
+        .exec(transaction)
+        .await?;
+
+        let remaining = batch.remaining - taken;
+        let mut active_model: batch::ActiveModel = batch.into();
+        active_model.remaining = ActiveValue::Set(remaining);
+        save_batch_transaction::<StockOutError>(transaction, active_model).await?;
+    }
+
+    Ok(left.max(0))
+}
+
+pub enum StockOutError {
+    Db(DbErr),
+    InvalidNumber(i32),
+    InsufficientStock { available: i32, requested: i32 },
+    AlreadyReversed(i32),
+    Version(VersionConflict),
+    Overflow(StockOverflow),
+}
+
+impl From<DbErr> for StockOutError {
+    fn from(err: DbErr) -> Self {
+        StockOutError::Db(err)
+    }
+}
+
+impl From<VersionConflict> for StockOutError {
+    fn from(conflict: VersionConflict) -> Self {
+        StockOutError::Version(conflict)
+    }
 }
 
-pub async fn insert_or_update_stock_out(
+impl From<StockOverflow> for StockOutError {
+    fn from(overflow: StockOverflow) -> Self {
+        StockOutError::Overflow(overflow)
+    }
+}
+
+/// Records a stock-out and draws it from the item's enabled batches,
+/// earliest expiry first. Returns the id of the new record.
+///
+/// A stock-out larger than what those batches hold is refused unless
+/// `override_reason` is given, in which case the override is recorded
+/// together with the reason and the part no batch covered.
+pub async fn insert_stock_out_transaction(
+    transaction: &DatabaseTransaction,
+    stock_out: stock_out::Model,
+    override_reason: Option<String>,
+    context: &AuditContext,
+) -> Result<i32, StockOutError> {
+    if stock_out.number <= 0 {
+        return Err(StockOutError::InvalidNumber(stock_out.number));
+    }
+
+    let item = match Item::find_by_id(stock_out.item_id).one(transaction).await? {
+        Some(item) => item,
+        None => return Err(DbErr::RecordNotFound(String::from("Item not found.")).into()),
+    };
+    let batches = get_stocked_batches(transaction, item.id).await?;
+    let available = total_stock(item.id, batches.iter().map(|batch| batch.remaining))?;
+    if available < stock_out.number && override_reason.is_none() {
+        return Err(StockOutError::InsufficientStock {
+            available,
+            requested: stock_out.number,
+        });
+    }
+
+    let stock_out = stock_out::Model {
+        created_at: chrono::Local::now().naive_local(),
+        reversed_at: None,
+        ..stock_out
+    };
+    let stock_out_id = StockOut::insert(stock_out::ActiveModel {
+        id: ActiveValue::NotSet,
+        date: ActiveValue::Set(stock_out.date),
+        number: ActiveValue::Set(stock_out.number),
+        item_id: ActiveValue::Set(stock_out.item_id),
+        created_at: ActiveValue::Set(stock_out.created_at),
+        operator: ActiveValue::Set(stock_out.operator.clone()),
+        reason: ActiveValue::Set(stock_out.reason.clone()),
+        reference: ActiveValue::Set(stock_out.reference.clone()),
+        reversed_at: ActiveValue::Set(None),
+    })
+    .exec(transaction)
+    .await?
+    .last_insert_id;
+
+    let unallocated =
+        allocate_stock_out(transaction, stock_out_id, batches, stock_out.number).await?;
+
+    if unallocated > 0 {
+        if let Some(reason) = override_reason {
+            StockOutOverride::insert(stock_out_override::ActiveModel {
+                id: ActiveValue::NotSet,
+                stock_out_id: ActiveValue::Set(stock_out_id),
+                item_id: ActiveValue::Set(item.id),
+                created_at: ActiveValue::Set(chrono::Local::now().naive_local()),
+                available: ActiveValue::Set(available),
+                number: ActiveValue::Set(stock_out.number),
+                reason: ActiveValue::Set(reason),
+                unallocated: ActiveValue::Set(unallocated),
+            })
+            .exec(transaction)
+            .await?;
+        }
+    }
+
+    let number = change_stock(item.id, item.number, -stock_out.number)?;
+    let expiration = get_stock_expiration(transaction, item.id).await?;
+    let mut active_model: item::ActiveModel = item.into();
+    active_model.number = ActiveValue::Set(number);
+    active_model.expiration = ActiveValue::Set(expiration);
+    save_item_transaction::<StockOutError>(transaction, active_model).await?;
+
+    audit::record_transaction(
+        transaction,
+        context,
+        AuditEntity::StockOut,
+        stock_out_id,
+        AuditAction::Create,
+        None,
+        Some(&stock_out::Model {
+            id: stock_out_id,
+            ..stock_out
+        }),
+    )
+    .await?;
+
+    Ok(stock_out_id)
+}
+
+pub async fn insert_stock_out(
     db: &DatabaseConnection,
     stock_out: stock_out::Model,
-) -> Result<(), DbErr> {
+    override_reason: Option<String>,
+    context: &AuditContext,
+) -> Result<i32, StockOutError> {
     let transaction = db.begin().await?;
+    let stock_out_id =
+        insert_stock_out_transaction(&transaction, stock_out, override_reason, context).await?;
+    transaction.commit().await?;
 
-    let mut stock_outs = get_stock_out_by_item_id(&transaction, stock_out.item_id).await?;
-    stock_outs.sort_by(|a, b| a.date.cmp(&b.date));
-
-    let exists = stock_outs.iter().fold(
-        false,
-        |ans, s| if s.date == stock_out.date { true } else { ans },
-    );
-    let next_id = get_max_id(&transaction).await? + 1;
-
-    if stock_outs.len() == 0 || !exists {
-        StockOut::insert(stock_out::ActiveModel {
-            id: ActiveValue::Set(stock_out.id.max(next_id)),
-            date: ActiveValue::Set(stock_out.date),
-            number: ActiveValue::Set(stock_out.number),
-            item_id: ActiveValue::Set(stock_out.item_id),
-        })
-        .exec(&transaction)
-        .await?;
-    } else {
-        let last_record = stock_outs
-            .iter()
-            .filter(|s| s.date == stock_out.date)
-            .last()
-            .unwrap();
-
-        let active_model = stock_out::ActiveModel {
-            id: ActiveValue::Unchanged(last_record.id),
-            date: ActiveValue::Unchanged(last_record.date),
-            number: ActiveValue::Set(last_record.number + stock_out.number),
-            item_id: ActiveValue::Unchanged(last_record.item_id),
+    Ok(stock_out_id)
+}
+
+/// Reverses a stock-out: its units go back to the batches they were drawn
+/// from and to the item's stock. Units drawn from batches that have since
+/// been disabled stay written off with the rest of those batches. The record
+/// itself is kept, marked as reversed.
+pub async fn reverse_stock_out(
+    db: &DatabaseConnection,
+    id: i32,
+    context: &AuditContext,
+) -> Result<(), StockOutError> {
+    let transaction = db.begin().await?;
+
+    let stock_out = match StockOut::find_by_id(id).one(&transaction).await? {
+        Some(stock_out) => stock_out,
+        None => return Err(DbErr::RecordNotFound(String::from("Stock-out not found.")).into()),
+    };
+    if stock_out.reversed_at.is_some() {
+        return Err(StockOutError::AlreadyReversed(id));
+    }
+
+    // What no batch covered was taken from the item's stock alone.
+    let mut restored = StockOutOverride::find()
+        .filter(stock_out_override::Column::StockOutId.eq(id))
+        .one(&transaction)
+        .await?
+        .map_or(0, |stock_out_override| stock_out_override.unallocated);
+    for allocation in get_stock_out_batches(&transaction, id).await? {
+        let batch = match Batch::find_by_id(allocation.batch_id)
+            .one(&transaction)
+            .await?
+        {
+            Some(batch) => batch,
+            None => return Err(DbErr::RecordNotFound(String::from("Batch not found!")).into()),
         };
-        active_model.update(&transaction).await?;
+        if batch.disabled {
+            continue;
+        }
+        restored += allocation.number;
+        let remaining = batch.remaining + allocation.number;
+        let mut active_model: batch::ActiveModel = batch.into();
+        active_model.remaining = ActiveValue::Set(remaining);
+        save_batch_transaction::<StockOutError>(&transaction, active_model).await?;
     }
 
     let item = match Item::find_by_id(stock_out.item_id)
@@ -97,20 +329,31 @@ pub async fn insert_or_update_stock_out(
         .await?
     {
         Some(item) => item,
-        None => return Err(DbErr::RecordNotFound(String::from("Item not found."))),
+        None => return Err(DbErr::RecordNotFound(String::from("Item not found.")).into()),
     };
+    let number = change_stock(item.id, item.number, restored)?;
+    let expiration = get_stock_expiration(&transaction, item.id).await?;
+    let mut active_model: item::ActiveModel = item.into();
+    active_model.number = ActiveValue::Set(number);
+    active_model.expiration = ActiveValue::Set(expiration);
+    save_item_transaction::<StockOutError>(&transaction, active_model).await?;
 
-    let active_model = item::ActiveModel {
-        id: ActiveValue::Unchanged(item.id),
-        name: ActiveValue::Unchanged(item.name),
-        specification: ActiveValue::Unchanged(item.specification),
-        unit: ActiveValue::Unchanged(item.unit),
-        manufacturer: ActiveValue::Unchanged(item.manufacturer),
-        number: ActiveValue::Set(item.number - stock_out.number),
-        price: ActiveValue::Unchanged(item.price),
-        expiration: ActiveValue::Unchanged(item.expiration),
-    };
-    active_model.update(&transaction).await?;
+    let mut active_model: stock_out::ActiveModel = stock_out.clone().into();
+    active_model.reversed_at = ActiveValue::Set(Some(chrono::Local::now().naive_local()));
+    let reversed = active_model.update(&transaction).await?;
+
+    audit::record_transaction(
+        &transaction,
+        context,
+        AuditEntity::StockOut,
+        id,
+        AuditAction::Reverse,
+        Some(&stock_out),
+        Some(&reversed),
+    )
+    .await?;
+
+    transaction.commit().await?;
 
-    transaction.commit().await
+    Ok(())
 }
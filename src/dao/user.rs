@@ -0,0 +1,88 @@
+use crate::models::{prelude::*, sea_orm_active_enums::UserRole, session, user};
+use sea_orm::{
+    ActiveValue, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+};
+
+pub async fn get_users(db: &DatabaseConnection) -> Result<Vec<user::Model>, DbErr> {
+    User::find().order_by_asc(user::Column::Id).all(db).await
+}
+
+pub async fn get_user_by_username(
+    db: &DatabaseConnection,
+    username: &str,
+) -> Result<Option<user::Model>, DbErr> {
+    User::find()
+        .filter(user::Column::Username.eq(username))
+        .one(db)
+        .await
+}
+
+/// Creates a user with an already hashed password and returns its id.
+pub async fn insert_user(
+    db: &DatabaseConnection,
+    username: String,
+    password_hash: String,
+    role: UserRole,
+) -> Result<i32, DbErr> {
+    let result = User::insert(user::ActiveModel {
+        id: ActiveValue::NotSet,
+        username: ActiveValue::Set(username),
+        password_hash: ActiveValue::Set(password_hash),
+        role: ActiveValue::Set(role),
+        created_at: ActiveValue::Set(chrono::Local::now().naive_local()),
+    })
+    .exec(db)
+    .await?;
+
+    Ok(result.last_insert_id)
+}
+
+/// Records a session for `user_id`, dropping the ones that have expired.
+pub async fn insert_session(
+    db: &DatabaseConnection,
+    user_id: i32,
+    token_hash: String,
+    expires_at: chrono::NaiveDateTime,
+) -> Result<(), DbErr> {
+    let now = chrono::Local::now().naive_local();
+    Session::delete_many()
+        .filter(session::Column::ExpiresAt.lte(now))
+        .exec(db)
+        .await?;
+
+    Session::insert(session::ActiveModel {
+        id: ActiveValue::NotSet,
+        user_id: ActiveValue::Set(user_id),
+        token_hash: ActiveValue::Set(token_hash),
+        created_at: ActiveValue::Set(now),
+        expires_at: ActiveValue::Set(expires_at),
+    })
+    .exec(db)
+    .await?;
+
+    Ok(())
+}
+
+/// The user an unexpired session belongs to.
+pub async fn get_session_user(
+    db: &DatabaseConnection,
+    token_hash: &str,
+) -> Result<Option<user::Model>, DbErr> {
+    let session = Session::find()
+        .filter(session::Column::TokenHash.eq(token_hash))
+        .filter(session::Column::ExpiresAt.gt(chrono::Local::now().naive_local()))
+        .find_also_related(User)
+        .one(db)
+        .await?;
+
+    Ok(session.and_then(|(_, user)| user))
+}
+
+pub async fn delete_session(db: &DatabaseConnection, token_hash: &str) -> Result<(), DbErr> {
+    Session::delete_many()
+        .filter(session::Column::TokenHash.eq(token_hash))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use crate::models::{batch, item, prelude::*, sea_orm_active_enums::WriteOffReason, write_off};
+use sea_orm::{
+    ActiveValue, ColumnTrait, DatabaseConnection, DatabaseTransaction, DbErr, EntityTrait,
+    FromQueryResult, JoinType, QueryFilter, QueryOrder, QuerySelect, RelationTrait,
+};
+
+#[derive(rocket_okapi::JsonSchema, rocket::serde::Serialize, rocket::serde::Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[derive(Debug, FromQueryResult)]
+pub struct WriteOffAndItem {
+    pub id: i32,
+    pub batch_id: i32,
+    pub item_id: i32,
+    pub created_at: chrono::NaiveDateTime,
+    pub number: i32,
+    pub reason: WriteOffReason,
+
+    pub name: String,
+    pub specification: Option<String>,
+    pub unit: Option<String>,
+    pub manufacturer: String,
+    pub price: f32,
+    /// Expiration of the written-off batch.
+    pub expiration: chrono::NaiveDate,
+}
+
+/// Lists the write-offs recorded between two dates, both ends inclusive.
+pub async fn get_write_offs(
+    db: &DatabaseConnection,
+    from_date: chrono::NaiveDate,
+    to_date: chrono::NaiveDate,
+) -> Result<Vec<WriteOffAndItem>, DbErr> {
+    // The last day chrono can represent has no next day to stop before.
+    let until = match to_date.succ_opt() {
+        Some(next) => write_off::Column::CreatedAt.lt(next.and_hms(0, 0, 0)),
+        None => write_off::Column::CreatedAt.lte(to_date.and_hms(23, 59, 59)),
+    };
+    WriteOff::find()
+        .column(item::Column::Name)
+        .column(item::Column::Specification)
+        .column(item::Column::Unit)
+        .column(item::Column::Manufacturer)
+        .column(item::Column::Price)
+        .column(batch::Column::Expiration)
+        .join(JoinType::InnerJoin, write_off::Relation::Item.def())
+        .join(JoinType::InnerJoin, write_off::Relation::Batch.def())
+        .filter(write_off::Column::CreatedAt.gte(from_date.and_hms(0, 0, 0)))
+        .filter(until)
+        .order_by_desc(write_off::Column::CreatedAt)
+        .order_by_desc(write_off::Column::Id)
+        .into_model::<WriteOffAndItem>()
+        .all(db)
+        .await
+}
+
+/// Writes off what is left of a batch.
+pub async fn write_off_transaction(
+    transaction: &DatabaseTransaction,
+    batch: &batch::Model,
+    reason: WriteOffReason,
+) -> Result<(), DbErr> {
+    WriteOff::insert(write_off::ActiveModel {
+        id: ActiveValue::NotSet,
+        batch_id: ActiveValue::Set(batch.id),
+        item_id: ActiveValue::Set(batch.item_id),
+        created_at: ActiveValue::Set(chrono::Local::now().naive_local()),
+        number: ActiveValue::Set(batch.remaining),
+        reason: ActiveValue::Set(reason),
+    })
+    .exec(transaction)
+    .await?;
+
+    Ok(())
+}
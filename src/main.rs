@@ -1,54 +1,143 @@
 mod controllers;
 mod dao;
+mod migration;
 mod models;
+#[cfg(test)]
+mod tests;
 
-use controllers::{batch, item, stock_out};
+use controllers::{
+    audit, auth, batch, error::ApiError, item, spreadsheet::ImportConfig, stock_out, write_off,
+};
 use dao::db::setup_db;
+use migration::{Migrator, MigratorTrait};
+use models::sea_orm_active_enums::UserRole;
 use rocket::{
-    catch, catchers, routes,
-    serde::json::{json, Value},
+    catch, catchers,
+    data::{ByteUnit, Limits},
+    figment::Figment,
+    http::Status,
+    routes, Build, Config, Request, Rocket,
 };
 use rocket_okapi::{
     openapi_get_routes,
     swagger_ui::{make_swagger_ui, SwaggerUIConfig},
 };
+use sea_orm::{ActiveEnum, DatabaseConnection};
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
 
 #[catch(404)]
-fn not_found() -> Value {
-    json!({
-      "error": {
-        "code": 404,
-        "reason": "Resource Not Found",
-        "description": "Error finding resource you requested."
-      }
-    })
+fn not_found() -> ApiError {
+    ApiError::NotFound(String::from("Error finding resource you requested."))
 }
 
-#[rocket::main]
-async fn main() {
-    let db = match setup_db().await {
-        Ok(db) => db,
-        Err(err) => panic!("Database error: {}.", err.to_string()),
+#[catch(401)]
+fn unauthorized() -> ApiError {
+    ApiError::Unauthorized(String::from(
+        "Log in first, and send the session token or cookie with each request.",
+    ))
+}
+
+#[catch(403)]
+fn forbidden() -> ApiError {
+    ApiError::Forbidden(String::from("Your role does not allow this request."))
+}
+
+#[catch(413)]
+fn payload_too_large(request: &Request) -> ApiError {
+    let max_upload_size = request
+        .rocket()
+        .state::<ImportConfig>()
+        .map_or_else(ByteUnit::default, |config| config.max_upload_size);
+    ApiError::PayloadTooLarge(format!(
+        "The request is too large; spreadsheet uploads may be up to {}.",
+        max_upload_size
+    ))
+}
+
+/// Answers every other error status Rocket raises, such as 422 for a JSON
+/// body that does not parse, with the same error body as the handlers.
+#[catch(default)]
+fn default_catcher(status: Status, _request: &Request) -> ApiError {
+    ApiError::from(status)
+}
+
+fn rocket(figment: Figment, db: DatabaseConnection) -> Rocket<Build> {
+    let import_config: ImportConfig = match figment.extract_inner("import") {
+        Ok(config) => config,
+        Err(err) if err.missing() => ImportConfig::default(),
+        Err(err) => panic!("Import configuration error: {}.", err),
+    };
+    let session_config: auth::SessionConfig = match figment.extract_inner("session") {
+        Ok(config) => config,
+        Err(err) if err.missing() => auth::SessionConfig::default(),
+        Err(err) => panic!("Session configuration error: {}.", err),
     };
+    // Uploads are multipart forms, so the form limit must admit the largest.
+    let data_form = figment
+        .extract_inner::<ByteUnit>("limits.data-form")
+        .unwrap_or(Limits::DATA_FORM)
+        .max(import_config.max_upload_size + ByteUnit::Kibibyte(64));
+    let figment = figment.merge(("limits.data-form", data_form));
 
-    let launch_result = rocket::build()
+    rocket::custom(figment)
         .manage(db)
-        .register("/", catchers![not_found])
-        .mount("/api", routes![batch::create_batch_from_xlsx])
+        .manage(import_config)
+        .manage(session_config)
+        .attach(audit::RequestIds)
+        .register(
+            "/",
+            catchers![
+                not_found,
+                unauthorized,
+                forbidden,
+                payload_too_large,
+                default_catcher
+            ],
+        )
+        .mount(
+            "/api",
+            routes![
+                batch::create_batch_from_xlsx,
+                stock_out::create_stock_out_from_xlsx
+            ],
+        )
         .mount(
             "/api",
             openapi_get_routes![
+                auth::login,
+                auth::logout,
+                auth::get_current_user,
+                auth::get_users,
+                auth::create_user,
                 item::get_items,
+                item::export_items,
+                item::get_item,
                 item::create_item,
                 item::modify_item,
+                item::adjust_stock,
+                item::get_stock_adjustments,
+                item::get_stock_adjustment_batches,
+                item::archive_item,
+                item::restore_item,
                 item::delete_item,
                 stock_out::get_stock_out_and_items,
-                stock_out::insert_or_update_stock_out,
+                stock_out::export_stock_out_and_items,
+                stock_out::insert_stock_out,
+                stock_out::reverse_stock_out,
                 stock_out::get_stock_out_by_item_id,
+                stock_out::get_stock_out_by_item_id_at_stock_out,
+                stock_out::get_daily_stock_out_by_item_id,
+                stock_out::get_stock_out_batches,
                 batch::get_stock_in_and_items,
+                batch::export_stock_in_and_items,
                 batch::get_batches_and_items,
+                batch::export_batches_and_items,
+                batch::get_batch,
                 batch::create_batch,
-                batch::disable_batch
+                batch::modify_batch,
+                write_off::get_write_offs,
+                audit::get_audit
             ],
         )
         .mount(
@@ -58,8 +147,87 @@ async fn main() {
                 ..Default::default()
             }),
         )
-        .launch()
-        .await;
+}
+
+/// Reads a line from standard input. When that is a terminal, the prompt is
+/// shown and the typed text is not echoed, as far as `stty` allows.
+fn read_password(prompt: &str) -> std::io::Result<String> {
+    let terminal = std::io::stdin().is_terminal();
+    let stty = |arg: &str| {
+        Command::new("stty")
+            .arg(arg)
+            .stdin(Stdio::inherit())
+            .status()
+            .is_ok_and(|status| status.success())
+    };
+
+    let mut hidden = false;
+    if terminal {
+        print!("{}", prompt);
+        std::io::stdout().flush()?;
+        hidden = stty("-echo");
+    }
+    let mut password = String::new();
+    let read = std::io::stdin().read_line(&mut password);
+    if hidden {
+        stty("echo");
+        println!();
+    }
+    read?;
+
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Runs `create-user <username> <viewer|clerk|manager>`, prompting for the
+/// password, or reading it piped in on standard input. This is how the first
+/// manager is made.
+async fn create_user(db: &DatabaseConnection, args: &[String]) -> Result<i32, String> {
+    let usage = "Usage: create-user <username> <viewer|clerk|manager>";
+    let (username, role) = match args {
+        [username, role] => (username, role),
+        _ => return Err(usage.to_string()),
+    };
+    let role = UserRole::try_from_value(role).map_err(|_| usage.to_string())?;
+
+    let password = read_password(&format!("Password for {}: ", username))
+        .map_err(|_| String::from("Error reading the password."))?;
+
+    auth::insert_user(db, username.clone(), password, role)
+        .await
+        .map_err(|err| err.body().error.description)
+}
+
+#[rocket::main]
+async fn main() {
+    let db = match setup_db().await {
+        Ok(db) => db,
+        Err(err) => panic!("Database error: {}.", err),
+    };
+
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("migrate") {
+        if let Err(err) = migration::run_command(&db, args.get(2).map(String::as_str)).await {
+            panic!("Migration error: {}.", err);
+        }
+        return;
+    }
+
+    if let Err(err) = Migrator::up(&db, None).await {
+        panic!("Migration error: {}.", err);
+    }
+
+    if args.get(1).map(String::as_str) == Some("create-user") {
+        match create_user(&db, &args[2..]).await {
+            Ok(id) => println!("Created user {}.", id),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let launch_result = rocket(Config::figment(), db).launch().await;
 
     match launch_result {
         Ok(_) => println!("Shutdown successfully."),
@@ -0,0 +1,119 @@
+use crate::models::{batch, item, prelude::*, stock_out};
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Tables are created with IF NOT EXISTS so that databases set up by
+        // hand before migrations existed are adopted as they are.
+        manager
+            .create_table(
+                Table::create()
+                    .table(Item)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(item::Column::Id)
+                            .unsigned()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(item::Column::Name).text().not_null())
+                    .col(ColumnDef::new(item::Column::Specification).text())
+                    .col(ColumnDef::new(item::Column::Unit).text())
+                    .col(ColumnDef::new(item::Column::Manufacturer).text().not_null())
+                    .col(ColumnDef::new(item::Column::Number).integer().not_null())
+                    .col(
+                        ColumnDef::new(item::Column::Price)
+                            .float()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(item::Column::Expiration).date().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Batch)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(batch::Column::Id)
+                            .unsigned()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(batch::Column::Date).date().not_null())
+                    .col(ColumnDef::new(batch::Column::Number).integer().not_null())
+                    .col(ColumnDef::new(batch::Column::Expiration).date().not_null())
+                    .col(ColumnDef::new(batch::Column::Vendor).text())
+                    .col(
+                        ColumnDef::new(batch::Column::Disabled)
+                            .tiny_unsigned()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(batch::Column::ItemId).unsigned().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_batch_item")
+                            .from(Batch, batch::Column::ItemId)
+                            .to(Item, item::Column::Id)
+                            .on_update(ForeignKeyAction::NoAction)
+                            .on_delete(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(StockOut)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(stock_out::Column::Id)
+                            .unsigned()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(stock_out::Column::Date).date().not_null())
+                    .col(
+                        ColumnDef::new(stock_out::Column::Number)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(stock_out::Column::ItemId)
+                            .unsigned()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_stock_out_item")
+                            .from(StockOut, stock_out::Column::ItemId)
+                            .to(Item, item::Column::Id)
+                            .on_update(ForeignKeyAction::NoAction)
+                            .on_delete(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(StockOut).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Batch).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Item).to_owned())
+            .await
+    }
+}
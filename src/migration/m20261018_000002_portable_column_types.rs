@@ -0,0 +1,169 @@
+use crate::models::{batch, item, prelude::*, stock_out};
+use sea_orm::{ConnectionTrait, DbBackend, Statement};
+use sea_orm_migration::prelude::*;
+
+/// Switches ids to signed integers and `batch.disabled` to a boolean, which
+/// is what PostgreSQL can store and decode. SQLite is typeless enough that
+/// the tables created by the first migration already fit.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        match manager.get_database_backend() {
+            DbBackend::MySql => {
+                // MySQL refuses to change the type of a column that takes part
+                // in a foreign key, so the keys are dropped and recreated.
+                drop_foreign_keys(manager).await?;
+                modify_ids(manager, true).await?;
+                manager
+                    .alter_table(
+                        Table::alter()
+                            .table(Batch)
+                            .modify_column(
+                                ColumnDef::new(batch::Column::Disabled)
+                                    .boolean()
+                                    .not_null()
+                                    .default(false),
+                            )
+                            .to_owned(),
+                    )
+                    .await?;
+                create_foreign_keys(manager).await
+            }
+            DbBackend::Postgres => manager
+                .get_connection()
+                .execute(Statement::from_string(
+                    DbBackend::Postgres,
+                    r#"ALTER TABLE "batch"
+                            ALTER COLUMN "disabled" DROP DEFAULT,
+                            ALTER COLUMN "disabled" TYPE boolean USING "disabled" <> 0,
+                            ALTER COLUMN "disabled" SET DEFAULT false"#
+                        .to_owned(),
+                ))
+                .await
+                .map(|_| ()),
+            DbBackend::Sqlite => Ok(()),
+        }
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        match manager.get_database_backend() {
+            DbBackend::MySql => {
+                drop_foreign_keys(manager).await?;
+                modify_ids(manager, false).await?;
+                manager
+                    .alter_table(
+                        Table::alter()
+                            .table(Batch)
+                            .modify_column(
+                                ColumnDef::new(batch::Column::Disabled)
+                                    .tiny_unsigned()
+                                    .not_null()
+                                    .default(0),
+                            )
+                            .to_owned(),
+                    )
+                    .await?;
+                create_foreign_keys(manager).await
+            }
+            DbBackend::Postgres => manager
+                .get_connection()
+                .execute(Statement::from_string(
+                    DbBackend::Postgres,
+                    r#"ALTER TABLE "batch"
+                            ALTER COLUMN "disabled" DROP DEFAULT,
+                            ALTER COLUMN "disabled" TYPE smallint USING "disabled"::int,
+                            ALTER COLUMN "disabled" SET DEFAULT 0"#
+                        .to_owned(),
+                ))
+                .await
+                .map(|_| ()),
+            DbBackend::Sqlite => Ok(()),
+        }
+    }
+}
+
+fn id_column<C: Iden + 'static>(column: C, signed: bool) -> ColumnDef {
+    let mut def = ColumnDef::new(column);
+    if signed {
+        def.integer();
+    } else {
+        def.unsigned();
+    }
+    def.not_null().to_owned()
+}
+
+async fn modify_ids(manager: &SchemaManager<'_>, signed: bool) -> Result<(), DbErr> {
+    manager
+        .alter_table(
+            Table::alter()
+                .table(Item)
+                .modify_column(&mut id_column(item::Column::Id, signed))
+                .to_owned(),
+        )
+        .await?;
+    manager
+        .alter_table(
+            Table::alter()
+                .table(Batch)
+                .modify_column(&mut id_column(batch::Column::Id, signed))
+                .modify_column(&mut id_column(batch::Column::ItemId, signed))
+                .to_owned(),
+        )
+        .await?;
+    manager
+        .alter_table(
+            Table::alter()
+                .table(StockOut)
+                .modify_column(&mut id_column(stock_out::Column::Id, signed))
+                .modify_column(&mut id_column(stock_out::Column::ItemId, signed))
+                .to_owned(),
+        )
+        .await
+}
+
+async fn drop_foreign_keys(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    manager
+        .drop_foreign_key(
+            ForeignKey::drop()
+                .name("fk_batch_item")
+                .table(Batch)
+                .to_owned(),
+        )
+        .await?;
+    manager
+        .drop_foreign_key(
+            ForeignKey::drop()
+                .name("fk_stock_out_item")
+                .table(StockOut)
+                .to_owned(),
+        )
+        .await
+}
+
+async fn create_foreign_keys(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    manager
+        .create_foreign_key(
+            ForeignKey::create()
+                .name("fk_batch_item")
+                .from(Batch, batch::Column::ItemId)
+                .to(Item, item::Column::Id)
+                .on_update(ForeignKeyAction::NoAction)
+                .on_delete(ForeignKeyAction::NoAction)
+                .to_owned(),
+        )
+        .await?;
+    manager
+        .create_foreign_key(
+            ForeignKey::create()
+                .name("fk_stock_out_item")
+                .from(StockOut, stock_out::Column::ItemId)
+                .to(Item, item::Column::Id)
+                .on_update(ForeignKeyAction::NoAction)
+                .on_delete(ForeignKeyAction::NoAction)
+                .to_owned(),
+        )
+        .await
+}
@@ -0,0 +1,148 @@
+use crate::models::{batch, item, prelude::*, stock_out, stock_out_batch};
+use sea_orm::ConnectionTrait;
+use sea_orm_migration::prelude::*;
+
+/// Adds the per-batch remaining quantity and the table recording which
+/// batches each stock-out was drawn from.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Batch)
+                    .add_column(
+                        ColumnDef::new(batch::Column::Remaining)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(StockOutBatch)
+                    .col(
+                        ColumnDef::new(stock_out_batch::Column::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(stock_out_batch::Column::StockOutId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(stock_out_batch::Column::BatchId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(stock_out_batch::Column::Number)
+                            .integer()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_stock_out_batch_stock_out")
+                            .from(StockOutBatch, stock_out_batch::Column::StockOutId)
+                            .to(StockOut, stock_out::Column::Id)
+                            .on_update(ForeignKeyAction::NoAction)
+                            .on_delete(ForeignKeyAction::NoAction),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_stock_out_batch_batch")
+                            .from(StockOutBatch, stock_out_batch::Column::BatchId)
+                            .to(Batch, batch::Column::Id)
+                            .on_update(ForeignKeyAction::NoAction)
+                            .on_delete(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        backfill_remaining(manager).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(StockOutBatch).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Batch)
+                    .drop_column(batch::Column::Remaining)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Past stock-outs were never tied to batches, so the stock each item has on
+/// hand is spread over its enabled batches as if they had always been drawn
+/// down earliest expiry first: the latest-expiring batches keep their stock.
+async fn backfill_remaining(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+    let backend = manager.get_database_backend();
+
+    let items = db
+        .query_all(
+            backend.build(
+                Query::select()
+                    .columns([item::Column::Id, item::Column::Number])
+                    .from(Item),
+            ),
+        )
+        .await?;
+
+    for row in items {
+        let item_id: i32 = row.try_get("", "id")?;
+        let mut left: i32 = row.try_get("", "number")?;
+
+        let batches = db
+            .query_all(
+                backend.build(
+                    Query::select()
+                        .columns([batch::Column::Id, batch::Column::Number])
+                        .from(Batch)
+                        .and_where(Expr::col(batch::Column::ItemId).eq(item_id))
+                        .and_where(Expr::col(batch::Column::Disabled).eq(false))
+                        .order_by(batch::Column::Expiration, Order::Desc)
+                        .order_by(batch::Column::Id, Order::Desc),
+                ),
+            )
+            .await?;
+
+        for row in batches {
+            if left <= 0 {
+                break;
+            }
+            let batch_id: i32 = row.try_get("", "id")?;
+            let number: i32 = row.try_get("", "number")?;
+            let remaining = number.min(left);
+            left -= remaining;
+
+            db.execute(
+                backend.build(
+                    Query::update()
+                        .table(Batch)
+                        .value(batch::Column::Remaining, remaining.into())
+                        .and_where(Expr::col(batch::Column::Id).eq(batch_id)),
+                ),
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use crate::models::{item, prelude::*, stock_out, stock_out_override};
+use sea_orm_migration::prelude::*;
+
+/// Records stock-outs that were allowed to drive an item's stock below zero.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(StockOutOverride)
+                    .col(
+                        ColumnDef::new(stock_out_override::Column::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(stock_out_override::Column::StockOutId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(stock_out_override::Column::ItemId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(stock_out_override::Column::CreatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(stock_out_override::Column::Available)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(stock_out_override::Column::Number)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(stock_out_override::Column::Reason)
+                            .text()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_stock_out_override_stock_out")
+                            .from(StockOutOverride, stock_out_override::Column::StockOutId)
+                            .to(StockOut, stock_out::Column::Id)
+                            .on_update(ForeignKeyAction::NoAction)
+                            .on_delete(ForeignKeyAction::NoAction),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_stock_out_override_item")
+                            .from(StockOutOverride, stock_out_override::Column::ItemId)
+                            .to(Item, item::Column::Id)
+                            .on_update(ForeignKeyAction::NoAction)
+                            .on_delete(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(StockOutOverride).to_owned())
+            .await
+    }
+}
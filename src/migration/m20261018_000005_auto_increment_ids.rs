@@ -0,0 +1,165 @@
+use crate::models::{batch, item, prelude::*, stock_out, stock_out_batch, stock_out_override};
+use sea_orm::{ConnectionTrait, DbBackend, Statement};
+use sea_orm_migration::prelude::*;
+
+/// Lets the database assign the ids of items, batches and stock-outs.
+/// SQLite already does so for `INTEGER PRIMARY KEY` columns.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        match manager.get_database_backend() {
+            DbBackend::MySql => modify_ids(manager, true).await,
+            DbBackend::Postgres => {
+                for table in ["item", "batch", "stock_out"] {
+                    execute(
+                        manager,
+                        format!(
+                            r#"ALTER TABLE "{0}" ALTER COLUMN "id" ADD GENERATED BY DEFAULT AS IDENTITY"#,
+                            table
+                        ),
+                    )
+                    .await?;
+                    // Continue after the ids handed out by the old scheme.
+                    execute(
+                        manager,
+                        format!(
+                            r#"SELECT setval(pg_get_serial_sequence('"{0}"', 'id'), COALESCE(MAX("id"), 0) + 1, false) FROM "{0}""#,
+                            table
+                        ),
+                    )
+                    .await?;
+                }
+                Ok(())
+            }
+            DbBackend::Sqlite => Ok(()),
+        }
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        match manager.get_database_backend() {
+            DbBackend::MySql => modify_ids(manager, false).await,
+            DbBackend::Postgres => {
+                for table in ["item", "batch", "stock_out"] {
+                    execute(
+                        manager,
+                        format!(r#"ALTER TABLE "{}" ALTER COLUMN "id" DROP IDENTITY"#, table),
+                    )
+                    .await?;
+                }
+                Ok(())
+            }
+            DbBackend::Sqlite => Ok(()),
+        }
+    }
+}
+
+async fn execute(manager: &SchemaManager<'_>, sql: String) -> Result<(), DbErr> {
+    manager
+        .get_connection()
+        .execute(Statement::from_string(manager.get_database_backend(), sql))
+        .await
+        .map(|_| ())
+}
+
+/// Name and table of every foreign key referring to the ids, with the
+/// statement recreating it.
+fn foreign_keys() -> Vec<(&'static str, &'static str, ForeignKeyCreateStatement)> {
+    let foreign_key = |name: &'static str| {
+        ForeignKey::create()
+            .name(name)
+            .on_update(ForeignKeyAction::NoAction)
+            .on_delete(ForeignKeyAction::NoAction)
+            .to_owned()
+    };
+
+    vec![
+        (
+            "fk_batch_item",
+            "batch",
+            foreign_key("fk_batch_item")
+                .from(Batch, batch::Column::ItemId)
+                .to(Item, item::Column::Id)
+                .to_owned(),
+        ),
+        (
+            "fk_stock_out_item",
+            "stock_out",
+            foreign_key("fk_stock_out_item")
+                .from(StockOut, stock_out::Column::ItemId)
+                .to(Item, item::Column::Id)
+                .to_owned(),
+        ),
+        (
+            "fk_stock_out_batch_stock_out",
+            "stock_out_batch",
+            foreign_key("fk_stock_out_batch_stock_out")
+                .from(StockOutBatch, stock_out_batch::Column::StockOutId)
+                .to(StockOut, stock_out::Column::Id)
+                .to_owned(),
+        ),
+        (
+            "fk_stock_out_batch_batch",
+            "stock_out_batch",
+            foreign_key("fk_stock_out_batch_batch")
+                .from(StockOutBatch, stock_out_batch::Column::BatchId)
+                .to(Batch, batch::Column::Id)
+                .to_owned(),
+        ),
+        (
+            "fk_stock_out_override_stock_out",
+            "stock_out_override",
+            foreign_key("fk_stock_out_override_stock_out")
+                .from(StockOutOverride, stock_out_override::Column::StockOutId)
+                .to(StockOut, stock_out::Column::Id)
+                .to_owned(),
+        ),
+        (
+            "fk_stock_out_override_item",
+            "stock_out_override",
+            foreign_key("fk_stock_out_override_item")
+                .from(StockOutOverride, stock_out_override::Column::ItemId)
+                .to(Item, item::Column::Id)
+                .to_owned(),
+        ),
+    ]
+}
+
+/// MySQL refuses to modify a column that a foreign key refers to, so every
+/// key pointing at the ids is dropped and recreated around the change.
+async fn modify_ids(manager: &SchemaManager<'_>, auto_increment: bool) -> Result<(), DbErr> {
+    for (name, table, _) in foreign_keys() {
+        manager
+            .drop_foreign_key(
+                ForeignKey::drop()
+                    .name(name)
+                    .table(Alias::new(table))
+                    .to_owned(),
+            )
+            .await?;
+    }
+
+    for table in ["item", "batch", "stock_out"] {
+        let mut id = ColumnDef::new(Alias::new("id"));
+        id.integer().not_null();
+        if auto_increment {
+            id.auto_increment();
+        }
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Alias::new(table))
+                    .modify_column(&mut id)
+                    .to_owned(),
+            )
+            .await?;
+    }
+
+    for (_, _, foreign_key) in foreign_keys() {
+        manager.create_foreign_key(foreign_key).await?;
+    }
+
+    Ok(())
+}
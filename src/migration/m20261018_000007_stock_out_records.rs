@@ -0,0 +1,105 @@
+use crate::models::{prelude::*, stock_out};
+use sea_orm::ConnectionTrait;
+use sea_orm_migration::prelude::*;
+
+/// Keeps every stock-out as its own record, with who made it, why, and
+/// whether it was reversed.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only takes one column per ALTER TABLE.
+        let columns = [
+            ColumnDef::new(stock_out::Column::CreatedAt)
+                .date_time()
+                .not_null()
+                .default("1970-01-01 00:00:00")
+                .to_owned(),
+            ColumnDef::new(stock_out::Column::Operator)
+                .string_len(64)
+                .null()
+                .to_owned(),
+            ColumnDef::new(stock_out::Column::Reason)
+                .text()
+                .null()
+                .to_owned(),
+            ColumnDef::new(stock_out::Column::Reference)
+                .string_len(128)
+                .null()
+                .to_owned(),
+            ColumnDef::new(stock_out::Column::ReversedAt)
+                .date_time()
+                .null()
+                .to_owned(),
+        ];
+        for mut column in columns {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(StockOut)
+                        .add_column(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        backfill_created_at(manager).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            stock_out::Column::CreatedAt,
+            stock_out::Column::Operator,
+            stock_out::Column::Reason,
+            stock_out::Column::Reference,
+            stock_out::Column::ReversedAt,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(StockOut)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Stock-outs recorded before this migration only have a date; they are
+/// taken to have happened at its start.
+async fn backfill_created_at(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+    let backend = manager.get_database_backend();
+
+    let stock_outs = db
+        .query_all(
+            backend.build(
+                Query::select()
+                    .columns([stock_out::Column::Id, stock_out::Column::Date])
+                    .from(StockOut),
+            ),
+        )
+        .await?;
+
+    for row in stock_outs {
+        let id: i32 = row.try_get("", "id")?;
+        let date: chrono::NaiveDate = row.try_get("", "date")?;
+
+        db.execute(
+            backend.build(
+                Query::update()
+                    .table(StockOut)
+                    .value(stock_out::Column::CreatedAt, date.and_hms(0, 0, 0).into())
+                    .and_where(Expr::col(stock_out::Column::Id).eq(id)),
+            ),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use crate::models::{item, prelude::*};
+use sea_orm_migration::prelude::*;
+
+/// Gives items an optional SKU or barcode, unique where set.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Item)
+                    .add_column(ColumnDef::new(item::Column::Sku).string_len(64).null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Every backend lets a unique index hold any number of NULLs.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_item_sku")
+                    .table(Item)
+                    .col(item::Column::Sku)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(Index::drop().name("idx_item_sku").table(Item).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Item)
+                    .drop_column(item::Column::Sku)
+                    .to_owned(),
+            )
+            .await
+    }
+}
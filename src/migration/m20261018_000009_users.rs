@@ -0,0 +1,88 @@
+use crate::models::{prelude::*, session, user};
+use sea_orm_migration::prelude::*;
+
+/// User accounts and their login sessions.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(User)
+                    .col(
+                        ColumnDef::new(user::Column::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(user::Column::Username)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(user::Column::PasswordHash).text().not_null())
+                    .col(ColumnDef::new(user::Column::Role).string_len(16).not_null())
+                    .col(
+                        ColumnDef::new(user::Column::CreatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Session)
+                    .col(
+                        ColumnDef::new(session::Column::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(session::Column::UserId).integer().not_null())
+                    .col(
+                        ColumnDef::new(session::Column::TokenHash)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(session::Column::CreatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(session::Column::ExpiresAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_session_user")
+                            .from(Session, session::Column::UserId)
+                            .to(User, user::Column::Id)
+                            .on_update(ForeignKeyAction::NoAction)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Session).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(User).to_owned())
+            .await
+    }
+}
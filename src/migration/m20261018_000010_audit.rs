@@ -0,0 +1,80 @@
+use crate::models::{audit, prelude::*};
+use sea_orm_migration::prelude::*;
+
+/// The audit log of changes to items, batches and stock-outs.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Audit)
+                    .col(
+                        ColumnDef::new(audit::Column::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(audit::Column::CreatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(audit::Column::Actor)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(audit::Column::RequestId)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(audit::Column::Entity)
+                            .string_len(16)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(audit::Column::EntityId).integer().not_null())
+                    .col(
+                        ColumnDef::new(audit::Column::Action)
+                            .string_len(16)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(audit::Column::Before).json().null())
+                    .col(ColumnDef::new(audit::Column::After).json().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_audit_entity")
+                    .table(Audit)
+                    .col(audit::Column::Entity)
+                    .col(audit::Column::EntityId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_audit_created_at")
+                    .table(Audit)
+                    .col(audit::Column::CreatedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Audit).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,36 @@
+use crate::models::{item, prelude::*};
+use sea_orm_migration::prelude::*;
+
+/// Lets items be archived instead of deleted.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Item)
+                    .add_column(
+                        ColumnDef::new(item::Column::Archived)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Item)
+                    .drop_column(item::Column::Archived)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,70 @@
+use crate::models::{item, prelude::*, stock_adjustment};
+use sea_orm_migration::prelude::*;
+
+/// Records corrections of an item's stock, such as stocktake results.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(StockAdjustment)
+                    .col(
+                        ColumnDef::new(stock_adjustment::Column::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(stock_adjustment::Column::ItemId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(stock_adjustment::Column::CreatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(stock_adjustment::Column::Operator)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(stock_adjustment::Column::Previous)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(stock_adjustment::Column::Delta)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(stock_adjustment::Column::Reason)
+                            .text()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_stock_adjustment_item")
+                            .from(StockAdjustment, stock_adjustment::Column::ItemId)
+                            .to(Item, item::Column::Id)
+                            .on_update(ForeignKeyAction::NoAction)
+                            .on_delete(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(StockAdjustment).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,57 @@
+use crate::models::{batch, item, prelude::*};
+use sea_orm_migration::prelude::*;
+
+/// Versions items and batches, so that concurrent changes are detected.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Item)
+                    .add_column(
+                        ColumnDef::new(item::Column::Version)
+                            .integer()
+                            .not_null()
+                            .default(1),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Batch)
+                    .add_column(
+                        ColumnDef::new(batch::Column::Version)
+                            .integer()
+                            .not_null()
+                            .default(1),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Batch)
+                    .drop_column(batch::Column::Version)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Item)
+                    .drop_column(item::Column::Version)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,36 @@
+use crate::models::{prelude::*, stock_out_override};
+use sea_orm_migration::prelude::*;
+
+/// Records how much of an overridden stock-out no batch could cover.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(StockOutOverride)
+                    .add_column(
+                        ColumnDef::new(stock_out_override::Column::Unallocated)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(StockOutOverride)
+                    .drop_column(stock_out_override::Column::Unallocated)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,69 @@
+use crate::models::{batch, prelude::*, stock_adjustment, stock_adjustment_batch};
+use sea_orm_migration::prelude::*;
+
+/// Records which batches each stock adjustment added to or took from.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(StockAdjustmentBatch)
+                    .col(
+                        ColumnDef::new(stock_adjustment_batch::Column::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(stock_adjustment_batch::Column::StockAdjustmentId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(stock_adjustment_batch::Column::BatchId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(stock_adjustment_batch::Column::Number)
+                            .integer()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_stock_adjustment_batch_stock_adjustment")
+                            .from(
+                                StockAdjustmentBatch,
+                                stock_adjustment_batch::Column::StockAdjustmentId,
+                            )
+                            .to(StockAdjustment, stock_adjustment::Column::Id)
+                            .on_update(ForeignKeyAction::NoAction)
+                            .on_delete(ForeignKeyAction::NoAction),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_stock_adjustment_batch_batch")
+                            .from(
+                                StockAdjustmentBatch,
+                                stock_adjustment_batch::Column::BatchId,
+                            )
+                            .to(Batch, batch::Column::Id)
+                            .on_update(ForeignKeyAction::NoAction)
+                            .on_delete(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(StockAdjustmentBatch).to_owned())
+            .await
+    }
+}
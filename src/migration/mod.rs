@@ -0,0 +1,73 @@
+use sea_orm::DatabaseConnection;
+pub use sea_orm_migration::prelude::*;
+
+mod m20261018_000001_create_tables;
+mod m20261018_000002_portable_column_types;
+mod m20261018_000003_stock_out_batch;
+mod m20261018_000004_stock_out_override;
+mod m20261018_000005_auto_increment_ids;
+mod m20261018_000006_write_off;
+mod m20261018_000007_stock_out_records;
+mod m20261018_000008_item_sku;
+mod m20261018_000009_users;
+mod m20261018_000010_audit;
+mod m20261018_000011_item_archived;
+mod m20261018_000012_stock_adjustment;
+mod m20261018_000013_versions;
+mod m20261018_000014_override_unallocated;
+mod m20261018_000015_stock_adjustment_batch;
+
+pub struct Migrator;
+
+#[async_trait::async_trait]
+impl MigratorTrait for Migrator {
+    fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+        vec![
+            Box::new(m20261018_000001_create_tables::Migration),
+            Box::new(m20261018_000002_portable_column_types::Migration),
+            Box::new(m20261018_000003_stock_out_batch::Migration),
+            Box::new(m20261018_000004_stock_out_override::Migration),
+            Box::new(m20261018_000005_auto_increment_ids::Migration),
+            Box::new(m20261018_000006_write_off::Migration),
+            Box::new(m20261018_000007_stock_out_records::Migration),
+            Box::new(m20261018_000008_item_sku::Migration),
+            Box::new(m20261018_000009_users::Migration),
+            Box::new(m20261018_000010_audit::Migration),
+            Box::new(m20261018_000011_item_archived::Migration),
+            Box::new(m20261018_000012_stock_adjustment::Migration),
+            Box::new(m20261018_000013_versions::Migration),
+            Box::new(m20261018_000014_override_unallocated::Migration),
+            Box::new(m20261018_000015_stock_adjustment_batch::Migration),
+        ]
+    }
+}
+
+/// Runs `migrate [up|down|status]` from the command line. Without an action
+/// all pending migrations are applied.
+pub async fn run_command(db: &DatabaseConnection, action: Option<&str>) -> Result<(), DbErr> {
+    match action.unwrap_or("up") {
+        "up" => Migrator::up(db, None).await,
+        "down" => Migrator::down(db, Some(1)).await,
+        "status" => {
+            let applied: Vec<String> = Migrator::get_migration_models(db)
+                .await?
+                .into_iter()
+                .map(|model| model.version)
+                .collect();
+
+            for migration in Migrator::migrations() {
+                let status = if applied.iter().any(|v| v == migration.name()) {
+                    "Applied"
+                } else {
+                    "Pending"
+                };
+                println!("{:<8} {}", status, migration.name());
+            }
+            Ok(())
+        }
+        action => Err(DbErr::Custom(format!(
+            "Unknown migrate action '{}', expected up, down or status.",
+            action
+        ))),
+    }
+}
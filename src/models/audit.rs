@@ -0,0 +1,30 @@
+use super::sea_orm_active_enums::{AuditAction, AuditEntity};
+use sea_orm::entity::prelude::*;
+
+/// One change to an item, batch or stock-out. Entries are only ever added.
+#[derive(rocket_okapi::JsonSchema, rocket::serde::Serialize, rocket::serde::Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "audit")]
+#[schemars(rename = "Audit")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub created_at: DateTime,
+    /// Username of whoever made the change.
+    pub actor: String,
+    /// The `X-Request-Id` of the request that made the change.
+    pub request_id: String,
+    pub entity: AuditEntity,
+    pub entity_id: i32,
+    pub action: AuditAction,
+    /// The record before the change; absent for creations.
+    pub before: Option<Json>,
+    /// The record after the change; absent for deletions.
+    pub after: Option<Json>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
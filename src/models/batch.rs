@@ -8,15 +8,22 @@ use sea_orm::entity::prelude::*;
 #[sea_orm(table_name = "batch")]
 #[schemars(rename = "Batch")]
 pub struct Model {
-    #[sea_orm(primary_key, auto_increment = false)]
-    pub id: u32,
+    #[sea_orm(primary_key)]
+    #[serde(default)]
+    pub id: i32,
     pub date: Date,
     pub number: i32,
     pub expiration: Date,
     #[sea_orm(column_type = "Text", nullable)]
     pub vendor: Option<String>,
-    pub disabled: u8,
-    pub item_id: u32,
+    pub disabled: bool,
+    pub item_id: i32,
+    /// Quantity of this batch not yet drawn down by stock-outs.
+    #[serde(default)]
+    pub remaining: i32,
+    /// Goes up with every change; sent as the batch's `ETag`.
+    #[serde(default)]
+    pub version: i32,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -29,6 +36,10 @@ pub enum Relation {
         on_delete = "NoAction"
     )]
     Item,
+    #[sea_orm(has_many = "super::stock_out_batch::Entity")]
+    StockOutBatch,
+    #[sea_orm(has_many = "super::write_off::Entity")]
+    WriteOff,
 }
 
 impl Related<super::item::Entity> for Entity {
@@ -37,4 +48,16 @@ impl Related<super::item::Entity> for Entity {
     }
 }
 
+impl Related<super::stock_out_batch::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::StockOutBatch.def()
+    }
+}
+
+impl Related<super::write_off::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::WriteOff.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
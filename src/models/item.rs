@@ -8,8 +8,9 @@ use sea_orm::entity::prelude::*;
 #[sea_orm(table_name = "item")]
 #[schemars(rename = "Item")]
 pub struct Model {
-    #[sea_orm(primary_key, auto_increment = false)]
-    pub id: u32,
+    #[sea_orm(primary_key)]
+    #[serde(default)]
+    pub id: i32,
     #[sea_orm(column_type = "Text")]
     pub name: String,
     #[sea_orm(column_type = "Text", nullable)]
@@ -21,6 +22,17 @@ pub struct Model {
     pub number: i32,
     pub price: f32,
     pub expiration: Date,
+    /// Optional SKU or barcode, unique among items.
+    #[sea_orm(unique)]
+    #[serde(default)]
+    pub sku: Option<String>,
+    /// Archived items are hidden from the item list and from imports, but
+    /// keep their batches and stock-outs.
+    #[serde(default)]
+    pub archived: bool,
+    /// Goes up with every change; sent as the item's `ETag`.
+    #[serde(default)]
+    pub version: i32,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -29,6 +41,12 @@ pub enum Relation {
     Batch,
     #[sea_orm(has_many = "super::stock_out::Entity")]
     StockOut,
+    #[sea_orm(has_many = "super::stock_adjustment::Entity")]
+    StockAdjustment,
+    #[sea_orm(has_many = "super::stock_out_override::Entity")]
+    StockOutOverride,
+    #[sea_orm(has_many = "super::write_off::Entity")]
+    WriteOff,
 }
 
 impl Related<super::batch::Entity> for Entity {
@@ -43,4 +61,22 @@ impl Related<super::stock_out::Entity> for Entity {
     }
 }
 
+impl Related<super::stock_adjustment::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::StockAdjustment.def()
+    }
+}
+
+impl Related<super::stock_out_override::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::StockOutOverride.def()
+    }
+}
+
+impl Related<super::write_off::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::WriteOff.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
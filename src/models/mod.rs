@@ -2,6 +2,15 @@
 
 pub mod prelude;
 
+pub mod audit;
 pub mod batch;
 pub mod item;
+pub mod sea_orm_active_enums;
+pub mod session;
+pub mod stock_adjustment;
+pub mod stock_adjustment_batch;
 pub mod stock_out;
+pub mod stock_out_batch;
+pub mod stock_out_override;
+pub mod user;
+pub mod write_off;
@@ -1,5 +1,13 @@
 //! SeaORM Entity. Generated by sea-orm-codegen 0.9.1
 
+pub use super::audit::Entity as Audit;
 pub use super::batch::Entity as Batch;
 pub use super::item::Entity as Item;
+pub use super::session::Entity as Session;
+pub use super::stock_adjustment::Entity as StockAdjustment;
+pub use super::stock_adjustment_batch::Entity as StockAdjustmentBatch;
 pub use super::stock_out::Entity as StockOut;
+pub use super::stock_out_batch::Entity as StockOutBatch;
+pub use super::stock_out_override::Entity as StockOutOverride;
+pub use super::user::Entity as User;
+pub use super::write_off::Entity as WriteOff;
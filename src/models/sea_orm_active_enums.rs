@@ -0,0 +1,69 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(rocket_okapi::JsonSchema, rocket::serde::Serialize, rocket::serde::Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum WriteOffReason {
+    #[sea_orm(string_value = "expired")]
+    Expired,
+    #[sea_orm(string_value = "recalled")]
+    Recalled,
+    #[sea_orm(string_value = "damaged")]
+    Damaged,
+    #[sea_orm(string_value = "lost")]
+    Lost,
+}
+
+/// What a user may do, each role allowing everything the ones before it do.
+#[derive(rocket_okapi::JsonSchema, rocket::serde::Serialize, rocket::serde::Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum UserRole {
+    /// Reads stock, reports and exports.
+    #[sea_orm(string_value = "viewer")]
+    Viewer,
+    /// Also records items, stock-ins and stock-outs, and imports sheets.
+    #[sea_orm(string_value = "clerk")]
+    Clerk,
+    /// Also corrects batches and stock, reverses stock-outs, archives and
+    /// deletes items, and manages users.
+    #[sea_orm(string_value = "manager")]
+    Manager,
+}
+
+/// The kind of record an audit entry is about.
+#[derive(rocket_okapi::JsonSchema, rocket::serde::Serialize, rocket::serde::Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum AuditEntity {
+    #[sea_orm(string_value = "item")]
+    Item,
+    #[sea_orm(string_value = "batch")]
+    Batch,
+    #[sea_orm(string_value = "stock_out")]
+    StockOut,
+}
+
+#[derive(rocket_okapi::JsonSchema, rocket::serde::Serialize, rocket::serde::Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum AuditAction {
+    #[sea_orm(string_value = "create")]
+    Create,
+    #[sea_orm(string_value = "update")]
+    Update,
+    #[sea_orm(string_value = "delete")]
+    Delete,
+    #[sea_orm(string_value = "reverse")]
+    Reverse,
+    #[sea_orm(string_value = "archive")]
+    Archive,
+    #[sea_orm(string_value = "restore")]
+    Restore,
+    #[sea_orm(string_value = "adjust")]
+    Adjust,
+}
@@ -0,0 +1,39 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(rocket_okapi::JsonSchema, rocket::serde::Serialize, rocket::serde::Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "stock_adjustment")]
+#[schemars(rename = "StockAdjustment")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub item_id: i32,
+    pub created_at: DateTime,
+    pub operator: String,
+    /// The item's stock before the adjustment.
+    pub previous: i32,
+    pub delta: i32,
+    #[sea_orm(column_type = "Text")]
+    pub reason: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::item::Entity",
+        from = "Column::ItemId",
+        to = "super::item::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Item,
+}
+
+impl Related<super::item::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Item.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
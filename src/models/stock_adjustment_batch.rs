@@ -0,0 +1,49 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(rocket_okapi::JsonSchema, rocket::serde::Serialize, rocket::serde::Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "stock_adjustment_batch")]
+#[schemars(rename = "StockAdjustmentBatch")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub stock_adjustment_id: i32,
+    pub batch_id: i32,
+    /// Units added to the batch, or taken from it when negative.
+    pub number: i32,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::stock_adjustment::Entity",
+        from = "Column::StockAdjustmentId",
+        to = "super::stock_adjustment::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    StockAdjustment,
+    #[sea_orm(
+        belongs_to = "super::batch::Entity",
+        from = "Column::BatchId",
+        to = "super::batch::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Batch,
+}
+
+impl Related<super::stock_adjustment::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::StockAdjustment.def()
+    }
+}
+
+impl Related<super::batch::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Batch.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -8,11 +8,23 @@ use sea_orm::entity::prelude::*;
 #[sea_orm(table_name = "stock_out")]
 #[schemars(rename = "StockOut")]
 pub struct Model {
-    #[sea_orm(primary_key, auto_increment = false)]
-    pub id: u32,
+    #[sea_orm(primary_key)]
+    #[serde(default)]
+    pub id: i32,
     pub date: Date,
     pub number: i32,
-    pub item_id: u32,
+    pub item_id: i32,
+    /// When the stock-out was recorded; set by the server.
+    #[serde(default)]
+    pub created_at: DateTime,
+    pub operator: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub reason: Option<String>,
+    /// Customer, order or other reference for the stock-out.
+    pub reference: Option<String>,
+    /// Set once the stock-out has been reversed.
+    #[serde(default)]
+    pub reversed_at: Option<DateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -25,6 +37,10 @@ pub enum Relation {
         on_delete = "NoAction"
     )]
     Item,
+    #[sea_orm(has_many = "super::stock_out_batch::Entity")]
+    StockOutBatch,
+    #[sea_orm(has_many = "super::stock_out_override::Entity")]
+    StockOutOverride,
 }
 
 impl Related<super::item::Entity> for Entity {
@@ -33,4 +49,16 @@ impl Related<super::item::Entity> for Entity {
     }
 }
 
+impl Related<super::stock_out_batch::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::StockOutBatch.def()
+    }
+}
+
+impl Related<super::stock_out_override::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::StockOutOverride.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,48 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(rocket_okapi::JsonSchema, rocket::serde::Serialize, rocket::serde::Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "stock_out_batch")]
+#[schemars(rename = "StockOutBatch")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub stock_out_id: i32,
+    pub batch_id: i32,
+    pub number: i32,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::stock_out::Entity",
+        from = "Column::StockOutId",
+        to = "super::stock_out::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    StockOut,
+    #[sea_orm(
+        belongs_to = "super::batch::Entity",
+        from = "Column::BatchId",
+        to = "super::batch::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Batch,
+}
+
+impl Related<super::stock_out::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::StockOut.def()
+    }
+}
+
+impl Related<super::batch::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Batch.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,55 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(rocket_okapi::JsonSchema, rocket::serde::Serialize, rocket::serde::Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "stock_out_override")]
+#[schemars(rename = "StockOutOverride")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub stock_out_id: i32,
+    pub item_id: i32,
+    pub created_at: DateTime,
+    /// What the item's enabled batches held when the stock-out was posted.
+    pub available: i32,
+    pub number: i32,
+    /// The part of `number` that no batch covered.
+    pub unallocated: i32,
+    #[sea_orm(column_type = "Text")]
+    pub reason: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::stock_out::Entity",
+        from = "Column::StockOutId",
+        to = "super::stock_out::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    StockOut,
+    #[sea_orm(
+        belongs_to = "super::item::Entity",
+        from = "Column::ItemId",
+        to = "super::item::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Item,
+}
+
+impl Related<super::stock_out::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::StockOut.def()
+    }
+}
+
+impl Related<super::item::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Item.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
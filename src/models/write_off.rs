@@ -0,0 +1,51 @@
+use super::sea_orm_active_enums::WriteOffReason;
+use sea_orm::entity::prelude::*;
+
+#[derive(rocket_okapi::JsonSchema, rocket::serde::Serialize, rocket::serde::Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "write_off")]
+#[schemars(rename = "WriteOff")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub batch_id: i32,
+    pub item_id: i32,
+    pub created_at: DateTime,
+    pub number: i32,
+    pub reason: WriteOffReason,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::batch::Entity",
+        from = "Column::BatchId",
+        to = "super::batch::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Batch,
+    #[sea_orm(
+        belongs_to = "super::item::Entity",
+        from = "Column::ItemId",
+        to = "super::item::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Item,
+}
+
+impl Related<super::batch::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Batch.def()
+    }
+}
+
+impl Related<super::item::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Item.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,2164 @@
+use crate::migration::{Migrator, MigratorTrait};
+use crate::models::sea_orm_active_enums::UserRole;
+use rocket::{
+    figment::Figment,
+    http::{ContentType, Header, Status},
+    local::asynchronous::{Client, LocalResponse},
+    serde::json::{json, Value},
+    Config,
+};
+use sea_orm::{Database, DatabaseConnection};
+
+/// A client for the whole API, backed by a fresh in-memory SQLite database
+/// and logged in as a manager.
+pub async fn client() -> Client {
+    client_with(Config::figment()).await
+}
+
+/// A fresh in-memory SQLite database with a `manager` user.
+async fn database() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    Migrator::up(&db, None).await.unwrap();
+    crate::controllers::auth::insert_user(
+        &db,
+        String::from("manager"),
+        String::from(PASSWORD),
+        UserRole::Manager,
+    )
+    .await
+    .unwrap();
+    db
+}
+
+const PASSWORD: &str = "correct horse";
+
+/// A client logged in as the manager, keeping the session cookie.
+async fn client_with(figment: Figment) -> Client {
+    let client = Client::tracked(crate::rocket(figment, database().await))
+        .await
+        .unwrap();
+    let status = client
+        .post("/api/login")
+        .json(&json!({ "username": "manager", "password": PASSWORD }))
+        .dispatch()
+        .await
+        .status();
+    assert_eq!(status, Status::Ok);
+    client
+}
+
+async fn create_item(client: &Client) -> i32 {
+    client
+        .post("/api/items")
+        .json(&json!({
+            "id": 0, "name": "Aspirin", "specification": "100mg", "unit": "box",
+            "manufacturer": "Bayer", "number": 0, "price": 1.0, "expiration": "2099-12-31"
+        }))
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap()
+}
+
+async fn create_batch(
+    client: &Client,
+    item_id: i32,
+    number: i32,
+    expiration: &str,
+    disabled: bool,
+) {
+    let response = client
+        .post("/api/batches")
+        .json(&json!({
+            "id": 0, "date": "2023-01-01", "number": number, "expiration": expiration,
+            "vendor": null, "disabled": disabled, "item_id": item_id
+        }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+}
+
+/// An xlsx workbook whose first sheet holds `rows`, starting at A1.
+fn xlsx(rows: &[&[&str]]) -> Vec<u8> {
+    let mut book = umya_spreadsheet::new_file();
+    let sheet = book.get_sheet_mut(&0).unwrap();
+    for (row, values) in rows.iter().enumerate() {
+        for (col, value) in values.iter().enumerate() {
+            sheet
+                .get_cell_by_column_and_row_mut(&(col as u32 + 1), &(row as u32 + 1))
+                .set_value(*value);
+        }
+    }
+
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    umya_spreadsheet::writer::xlsx::write_writer(&book, &mut bytes).unwrap();
+    bytes.into_inner()
+}
+
+/// An ods spreadsheet whose first sheet holds `rows`; `YYYY-MM-DD` cells are
+/// stored as dates.
+fn ods(rows: &[&[&str]]) -> Vec<u8> {
+    use std::io::Write;
+
+    let mut content = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+        <office:document-content \
+        xmlns:office=\"urn:oasis:names:tc:opendocument:xmlns:office:1.0\" \
+        xmlns:table=\"urn:oasis:names:tc:opendocument:xmlns:table:1.0\" \
+        xmlns:text=\"urn:oasis:names:tc:opendocument:xmlns:text:1.0\">\
+        <office:body><office:spreadsheet><table:table table:name=\"Sheet1\">",
+    );
+    for row in rows {
+        content.push_str("<table:table-row>");
+        for value in row.iter() {
+            if chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok() {
+                content.push_str(&format!(
+                    "<table:table-cell office:value-type=\"date\" office:date-value=\"{}\">\
+                    <text:p>{}</text:p></table:table-cell>",
+                    value, value
+                ));
+            } else {
+                content.push_str(&format!(
+                    "<table:table-cell office:value-type=\"string\">\
+                    <text:p>{}</text:p></table:table-cell>",
+                    value
+                ));
+            }
+        }
+        content.push_str("</table:table-row>");
+    }
+    content.push_str("</table:table></office:spreadsheet></office:body></office:document-content>");
+
+    let manifest = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+        <manifest:manifest xmlns:manifest=\"urn:oasis:names:tc:opendocument:xmlns:manifest:1.0\">\
+        <manifest:file-entry manifest:full-path=\"/\" \
+        manifest:media-type=\"application/vnd.oasis.opendocument.spreadsheet\"/>\
+        <manifest:file-entry manifest:full-path=\"content.xml\" manifest:media-type=\"text/xml\"/>\
+        </manifest:manifest>";
+
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let stored =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for (name, data) in [
+        ("mimetype", "application/vnd.oasis.opendocument.spreadsheet"),
+        ("META-INF/manifest.xml", manifest),
+        ("content.xml", &content),
+    ] {
+        writer.start_file(name, stored).unwrap();
+        writer.write_all(data.as_bytes()).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+/// Posts `file` as the `file` field of a multipart form.
+async fn upload<'c>(client: &'c Client, uri: &str, file: &[u8]) -> LocalResponse<'c> {
+    upload_named(client, uri, "upload.xlsx", "application/octet-stream", file).await
+}
+
+async fn upload_named<'c>(
+    client: &'c Client,
+    uri: &str,
+    filename: &str,
+    content_type: &str,
+    file: &[u8],
+) -> LocalResponse<'c> {
+    let mut body = format!(
+        "--BOUNDARY\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
+        Content-Type: {}\r\n\r\n",
+        filename, content_type
+    )
+    .into_bytes();
+    body.extend_from_slice(file);
+    body.extend_from_slice(b"\r\n--BOUNDARY--\r\n");
+
+    client
+        .post(uri.to_string())
+        .header(ContentType::new("multipart", "form-data").with_params(("boundary", "BOUNDARY")))
+        .body(body)
+        .dispatch()
+        .await
+}
+
+const HOSTILE_DATES: [&str; 4] = [
+    "2022-01-01%22%20OR%201%3D1%20--%20",
+    "2022-01-01%27%3B%20DROP%20TABLE%20item%3B%20--",
+    "%22%29%20UNION%20SELECT%201%2C2%2C3%2C4%2C5%2C6%2C7%20--",
+    "2022-13-01",
+];
+
+#[rocket::async_test]
+async fn report_rejects_hostile_from_dates() {
+    let client = client().await;
+
+    for report in ["stock-in-and-items", "stock-out-and-items"] {
+        for from in HOSTILE_DATES {
+            let response = client
+                .get(format!("/api/{}?from={}&to=2022-12-31", report, from))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::BadRequest, "{} {}", report, from);
+        }
+    }
+
+    // The item table must have survived every attempt.
+    let response = client.get("/api/items").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn report_rejects_reversed_and_missing_ranges() {
+    let client = client().await;
+
+    for query in [
+        "from=2022-12-31&to=2022-01-01",
+        "to=2022-12-31",
+        "from=2022-01-01",
+    ] {
+        let response = client
+            .get(format!("/api/stock-in-and-items?{}", query))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest, "{}", query);
+    }
+}
+
+#[rocket::async_test]
+async fn report_accepts_valid_range() {
+    let client = client().await;
+
+    let response = client
+        .get("/api/stock-out-and-items?from=2022-01-01&to=2022-01-01")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().await.unwrap(), "[]");
+}
+
+#[rocket::async_test]
+async fn stock_out_draws_earliest_expiring_batches_first() {
+    let client = client().await;
+
+    let response = client
+        .post("/api/items")
+        .json(&json!({
+            "id": 0, "name": "Aspirin", "specification": "100mg", "unit": "box",
+            "manufacturer": "Bayer", "number": 0, "price": 1.0, "expiration": "2099-12-31"
+        }))
+        .dispatch()
+        .await;
+    let item_id: i32 = response.into_json().await.unwrap();
+
+    for expiration in ["2025-06-01", "2025-01-01", "2024-01-01"] {
+        create_batch(&client, item_id, 5, expiration, false).await;
+    }
+    let response = client
+        .patch("/api/batches/3")
+        .json(&json!({ "disabled": true, "reason": "recalled" }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .post("/api/stock-out")
+        .json(&json!({ "id": 0, "date": "2023-02-01", "number": 7, "item_id": item_id }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let page: Value = client
+        .get("/api/batches-and-items")
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    let remaining = |expiration: &str| {
+        page["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|b| b["expiration"] == expiration)
+            .map(|b| b["remaining"].as_i64().unwrap())
+            .unwrap()
+    };
+    assert_eq!(remaining("2025-01-01"), 0);
+    assert_eq!(remaining("2025-06-01"), 3);
+    assert_eq!(remaining("2024-01-01"), 0);
+
+    // The item expires with the earliest batch that still holds stock.
+    let items: Value = client
+        .get("/api/items")
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(items["items"][0]["expiration"], "2025-06-01");
+
+    let stock_outs: Vec<Value> = client
+        .get(format!("/api/items/{}/stock-outs", item_id))
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    let allocations: Vec<Value> = client
+        .get(format!("/api/stock-out/{}/batches", stock_outs[0]["id"]))
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    let numbers: Vec<i64> = allocations
+        .iter()
+        .map(|a| a["number"].as_i64().unwrap())
+        .collect();
+    assert_eq!(numbers, vec![5, 2]);
+}
+
+#[rocket::async_test]
+async fn stock_out_cannot_exceed_stock_without_override() {
+    let client = client().await;
+    let item_id = create_item(&client).await;
+    create_batch(&client, item_id, 4, "2025-01-01", false).await;
+
+    let post = |number: i32, query: &'static str| {
+        client
+            .post(format!("/api/stock-out{}", query))
+            .json(&json!({ "id": 0, "date": "2023-02-01", "number": number, "item_id": item_id }))
+            .dispatch()
+    };
+
+    assert_eq!(post(0, "").await.status(), Status::BadRequest);
+    assert_eq!(post(-3, "").await.status(), Status::BadRequest);
+
+    let response = post(5, "").await;
+    assert_eq!(response.status(), Status::Conflict);
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["error"]["available"], 4);
+
+    assert_eq!(
+        post(5, "?allow_negative=true").await.status(),
+        Status::BadRequest
+    );
+    assert_eq!(
+        post(5, "?allow_negative=true&reason=back-dated%20correction")
+            .await
+            .status(),
+        Status::Ok
+    );
+
+    let page: Value = client
+        .get("/api/items")
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(page["items"][0]["number"], -1);
+}
+
+/// Stock-outs are checked against what the batches hold, not the item's
+/// stock, which may have drifted from them in older data.
+#[rocket::async_test]
+async fn stock_outs_are_limited_to_batch_stock() {
+    use crate::models::{item, prelude::*};
+    use sea_orm::{sea_query::Expr, ColumnTrait, EntityTrait, QueryFilter};
+
+    let client = client().await;
+    let item_id = create_item(&client).await;
+    create_batch(&client, item_id, 4, "2025-01-01", false).await;
+    let db = client.rocket().state::<DatabaseConnection>().unwrap();
+    Item::update_many()
+        .col_expr(item::Column::Number, Expr::value(10))
+        .filter(item::Column::Id.eq(item_id))
+        .exec(db)
+        .await
+        .unwrap();
+
+    let post = |query: &'static str| {
+        client
+            .post(format!("/api/stock-out{}", query))
+            .json(&json!({ "id": 0, "date": "2023-02-01", "number": 6, "item_id": item_id }))
+            .dispatch()
+    };
+    let response = post("").await;
+    assert_eq!(response.status(), Status::Conflict);
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["error"]["available"], 4);
+
+    let response = post("?allow_negative=true&reason=counted%20wrong").await;
+    assert_eq!(response.status(), Status::Ok);
+    let overrides = StockOutOverride::find().all(db).await.unwrap();
+    assert_eq!((overrides[0].available, overrides[0].unallocated), (4, 2));
+    let item = Item::find_by_id(item_id).one(db).await.unwrap().unwrap();
+    assert_eq!(item.number, 4);
+
+    // Reversing returns the unallocated part to the item alone.
+    let response = client
+        .delete(format!("/api/stock-out/{}", overrides[0].stock_out_id))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let item = Item::find_by_id(item_id).one(db).await.unwrap().unwrap();
+    assert_eq!(item.number, 10);
+}
+
+#[rocket::async_test]
+async fn rocket_errors_have_json_bodies() {
+    let client = client().await;
+
+    let response = client
+        .post("/api/items")
+        .json(&json!({ "name": 1 }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["error"]["code"], 422);
+
+    let response = client
+        .post("/api/items")
+        .header(ContentType::JSON)
+        .body("{")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["error"]["code"], 400);
+}
+
+#[rocket::async_test]
+async fn database_errors_map_to_statuses() {
+    let client = client().await;
+
+    let response = client.delete("/api/items/42").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["error"]["code"], 404);
+
+    let response = client
+        .post("/api/batches")
+        .json(&json!({
+            "id": 0, "date": "2023-01-01", "number": 1, "expiration": "2024-01-01",
+            "vendor": null, "disabled": false, "item_id": 42
+        }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Conflict);
+}
+
+#[rocket::async_test]
+async fn create_ignores_client_supplied_ids() {
+    let client = client().await;
+
+    for expected in [1, 2] {
+        let id: i32 = client
+            .post("/api/items")
+            .json(&json!({
+                "id": 1000, "name": "Aspirin", "specification": null, "unit": null,
+                "manufacturer": "Bayer", "number": 0, "price": 1.0, "expiration": "2099-12-31"
+            }))
+            .dispatch()
+            .await
+            .into_json()
+            .await
+            .unwrap();
+        assert_eq!(id, expected);
+    }
+}
+
+#[rocket::async_test]
+async fn items_are_searched_sorted_and_paginated() {
+    let client = client().await;
+
+    for (name, manufacturer, number) in [
+        ("Aspirin", "Bayer", 3),
+        ("Ibuprofen", "Advil", 40),
+        ("Paracetamol 50%", "Bayer", 12),
+    ] {
+        let item_id: i32 = client
+            .post("/api/items")
+            .json(&json!({
+                "name": name, "specification": null, "unit": null, "manufacturer": manufacturer,
+                "price": 1.0
+            }))
+            .dispatch()
+            .await
+            .into_json()
+            .await
+            .unwrap();
+        create_batch(&client, item_id, number, "2099-12-31", false).await;
+    }
+
+    let names = |page: Value| -> Vec<String> {
+        page["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["name"].as_str().unwrap().to_string())
+            .collect()
+    };
+    let get = |query: &'static str| {
+        let client = &client;
+        async move {
+            let response = client.get(format!("/api/items?{}", query)).dispatch().await;
+            (response.status(), response.into_json::<Value>().await)
+        }
+    };
+
+    let (status, page) = get("q=bayer&sort=number&order=desc").await;
+    assert_eq!(status, Status::Ok);
+    let page = page.unwrap();
+    assert_eq!(page["total"], 2);
+    assert_eq!(names(page), ["Paracetamol 50%", "Aspirin"]);
+
+    // Wildcards in the search term are matched literally.
+    let (_, page) = get("q=%25").await;
+    assert_eq!(names(page.unwrap()), ["Paracetamol 50%"]);
+
+    let (_, page) = get("low_stock=12&sort=name").await;
+    assert_eq!(names(page.unwrap()), ["Aspirin", "Paracetamol 50%"]);
+
+    let (_, page) = get("sort=name&page=2&per_page=2").await;
+    let page = page.unwrap();
+    assert_eq!(page["total"], 3);
+    assert_eq!(names(page), ["Paracetamol 50%"]);
+
+    for query in [
+        "sort=price;drop",
+        "order=sideways",
+        "page=0",
+        "page=18446744073709551615",
+        "page=9223372036854775807&per_page=2",
+        "per_page=100000",
+        "expiring_before=tomorrow",
+    ] {
+        assert_eq!(get(query).await.0, Status::BadRequest, "{}", query);
+    }
+}
+
+#[rocket::async_test]
+async fn batches_are_filtered_and_paginated() {
+    let client = client().await;
+    let item_id = create_item(&client).await;
+    create_batch(&client, item_id, 5, "2024-01-31", false).await;
+    create_batch(&client, item_id, 5, "2024-06-30", true).await;
+    create_batch(&client, item_id, 5, "2025-01-31", false).await;
+
+    let get = |query: &'static str| {
+        let client = &client;
+        async move {
+            let response = client
+                .get(format!("/api/batches-and-items?{}", query))
+                .dispatch()
+                .await;
+            (response.status(), response.into_json::<Value>().await)
+        }
+    };
+    let expirations = |page: Value| -> Vec<String> {
+        page["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|batch| batch["expiration"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    let (status, page) = get("include_disabled=false&sort=expiration").await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(expirations(page.unwrap()), ["2024-01-31", "2025-01-31"]);
+
+    let (_, page) = get("expires_from=2024-02-01&expires_to=2024-12-31&q=ASPIR").await;
+    assert_eq!(expirations(page.unwrap()), ["2024-06-30"]);
+
+    let (_, page) = get("received_from=2023-01-01&sort=expiration&order=desc&per_page=1").await;
+    let page = page.unwrap();
+    assert_eq!(page["total"], 3);
+    assert_eq!(expirations(page), ["2025-01-31"]);
+
+    let (_, page) = get("q=ibuprofen").await;
+    assert_eq!(page.unwrap()["total"], 0);
+
+    for query in [
+        "expires_from=2025-01-01&expires_to=2024-01-01",
+        "received_to=yesterday",
+        "sort=name",
+    ] {
+        assert_eq!(get(query).await.0, Status::BadRequest, "{}", query);
+    }
+}
+
+#[rocket::async_test]
+async fn batch_edits_keep_item_in_step() {
+    let client = client().await;
+    let item_id = create_item(&client).await;
+    create_batch(&client, item_id, 5, "2024-01-31", false).await;
+    create_batch(&client, item_id, 5, "2025-01-31", false).await;
+    let response = client
+        .post("/api/stock-out")
+        .json(&json!({ "id": 0, "date": "2023-02-01", "number": 3, "item_id": item_id }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let patch = |id: i32, body: Value| {
+        let client = &client;
+        async move {
+            client
+                .patch(format!("/api/batches/{}", id))
+                .json(&body)
+                .dispatch()
+                .await
+                .status()
+        }
+    };
+    let item = || async {
+        let page: Value = client
+            .get("/api/items")
+            .dispatch()
+            .await
+            .into_json()
+            .await
+            .unwrap();
+        page["items"][0].clone()
+    };
+
+    assert_eq!(patch(1, json!({ "number": 2 })).await, Status::Conflict);
+    assert_eq!(patch(1, json!({ "number": 0 })).await, Status::BadRequest);
+    assert_eq!(
+        patch(42, json!({ "disabled": true })).await,
+        Status::NotFound
+    );
+
+    assert_eq!(
+        patch(1, json!({ "number": 8, "vendor": "Acme" })).await,
+        Status::Ok
+    );
+    assert_eq!(item().await["number"], 10);
+
+    assert_eq!(
+        patch(1, json!({ "disabled": true })).await,
+        Status::BadRequest
+    );
+    assert_eq!(
+        patch(1, json!({ "disabled": true, "reason": "expired" })).await,
+        Status::Ok
+    );
+    // The 5 left in the batch after the stock-out are written off.
+    assert_eq!(item().await["number"], 5);
+    assert_eq!(item().await["expiration"], "2025-01-31");
+
+    let today = chrono::Local::today().naive_local();
+    let write_offs: Vec<Value> = client
+        .get(format!("/api/write-offs?from={}&to={}", today, today))
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(write_offs.len(), 1);
+    assert_eq!(write_offs[0]["number"], 5);
+    assert_eq!(write_offs[0]["reason"], "expired");
+    assert_eq!(write_offs[0]["expiration"], "2024-01-31");
+    let response = client
+        .get(format!("/api/write-offs?from={}&to=%2B262143-12-31", today))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    // Re-enabling the emptied batch brings back neither stock nor expiration.
+    assert_eq!(patch(1, json!({ "disabled": false })).await, Status::Ok);
+    assert_eq!(item().await["number"], 5);
+    assert_eq!(item().await["expiration"], "2025-01-31");
+
+    let receive = |number: i32, disabled: bool| {
+        client
+            .post("/api/batches")
+            .json(&json!({
+                "id": 0, "date": "2023-03-01", "number": number, "expiration": "2026-06-30",
+                "vendor": null, "disabled": disabled, "item_id": item_id
+            }))
+            .dispatch()
+    };
+    assert_eq!(receive(-2, false).await.status(), Status::BadRequest);
+    assert_eq!(receive(0, false).await.status(), Status::BadRequest);
+    // A batch received disabled only counts once it is enabled.
+    assert_eq!(receive(4, true).await.status(), Status::Ok);
+    assert_eq!(item().await["number"], 5);
+    assert_eq!(patch(3, json!({ "disabled": false })).await, Status::Ok);
+    assert_eq!(item().await["number"], 9);
+}
+
+#[rocket::async_test]
+async fn stock_outs_are_kept_apart_and_reversible() {
+    let client = client().await;
+    let item_id = create_item(&client).await;
+    create_batch(&client, item_id, 10, "2025-01-01", false).await;
+
+    let mut ids = Vec::new();
+    for (number, reference) in [(2, "order-1"), (3, "order-2")] {
+        let id: i32 = client
+            .post("/api/stock-out")
+            .json(&json!({
+                "date": "2023-02-01", "number": number, "item_id": item_id,
+                "operator": "alice", "reason": "dispensed", "reference": reference
+            }))
+            .dispatch()
+            .await
+            .into_json()
+            .await
+            .unwrap();
+        ids.push(id);
+    }
+
+    let get = |uri: String| {
+        let client = &client;
+        async move {
+            client
+                .get(uri)
+                .dispatch()
+                .await
+                .into_json::<Value>()
+                .await
+                .unwrap()
+        }
+    };
+
+    let records = get(format!("/api/items/{}/stock-outs", item_id)).await;
+    assert_eq!(records.as_array().unwrap().len(), 2);
+    assert_eq!(get(format!("/api/stock-out/{}", item_id)).await, records);
+    let daily = get(format!("/api/items/{}/stock-outs/daily", item_id)).await;
+    assert_eq!(daily, json!([{ "date": "2023-02-01", "number": 5 }]));
+
+    let reverse = |id: i32| {
+        let client = &client;
+        async move {
+            client
+                .delete(format!("/api/stock-out/{}", id))
+                .dispatch()
+                .await
+                .status()
+        }
+    };
+    assert_eq!(reverse(ids[0]).await, Status::Ok);
+    assert_eq!(reverse(ids[0]).await, Status::Conflict);
+    assert_eq!(reverse(42).await, Status::NotFound);
+
+    let page = get(String::from("/api/items")).await;
+    assert_eq!(page["items"][0]["number"], 7);
+    let batches = get(String::from("/api/batches-and-items")).await;
+    assert_eq!(batches["items"][0]["remaining"], 7);
+
+    let daily = get(format!("/api/items/{}/stock-outs/daily", item_id)).await;
+    assert_eq!(daily, json!([{ "date": "2023-02-01", "number": 3 }]));
+    let report = get(String::from(
+        "/api/stock-out-and-items?from=2023-01-01&to=2023-12-31",
+    ))
+    .await;
+    assert_eq!(report[0]["number"], 3);
+
+    let records = get(format!("/api/items/{}/stock-outs", item_id)).await;
+    let reversed = records
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|r| r["id"] == ids[0])
+        .unwrap();
+    assert!(!reversed["reversed_at"].is_null());
+    assert_eq!(reversed["reference"], "order-1");
+
+    // Units drawn from a batch written off since stay written off.
+    let response = client
+        .patch("/api/batches/1")
+        .json(&json!({ "disabled": true, "reason": "expired" }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(reverse(ids[1]).await, Status::Ok);
+    let page = get(String::from("/api/items")).await;
+    assert_eq!(page["items"][0]["number"], 0);
+    assert_eq!(page["items"][0]["expiration"], "2099-12-31");
+    let batches = get(String::from("/api/batches-and-items?include_disabled=true")).await;
+    assert_eq!(batches["items"][0]["remaining"], 0);
+}
+
+#[rocket::async_test]
+async fn stock_beyond_i32_is_refused() {
+    let client = client().await;
+    let item_id = create_item(&client).await;
+    create_batch(&client, item_id, 2_000_000_000, "2025-01-01", false).await;
+
+    let response = client
+        .post("/api/batches")
+        .json(&json!({
+            "id": 0, "date": "2023-01-01", "number": 2_000_000_000, "expiration": "2025-06-30",
+            "vendor": null, "disabled": false, "item_id": item_id
+        }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Conflict);
+
+    let response = client
+        .post(format!("/api/items/{}/stock-adjustments", item_id))
+        .json(&json!({ "delta": i32::MAX, "batch_id": 1, "reason": "found" }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Conflict);
+
+    let response = client
+        .post("/api/batches")
+        .json(&json!({
+            "id": 0, "date": "2023-01-01", "number": 2_000_000_000, "expiration": "2025-06-30",
+            "vendor": null, "disabled": true, "item_id": item_id
+        }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let response = client
+        .patch("/api/batches/2")
+        .json(&json!({ "disabled": false }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Conflict);
+
+    let item: Value = client
+        .get(format!("/api/items/{}", item_id))
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(item["number"], 2_000_000_000);
+}
+
+#[rocket::async_test]
+async fn stock_outs_are_imported_all_or_nothing() {
+    let client = client().await;
+    let item_id = create_item(&client).await;
+    create_batch(&client, item_id, 10, "2025-01-01", false).await;
+
+    const HEADER: &[&str] = &["date", "name", "manufacturer", "number", "reference"];
+    let file = xlsx(&[
+        HEADER,
+        &["44927", "Aspirin", "Bayer", "5", "order-1"],
+        &["44927", "Aspirin", "Unknown", "1"],
+        &["44927", "Aspirin", "Bayer", "many"],
+        &["44927", "Aspirin", "Bayer", "100"],
+        &["", "", "", ""],
+        &["x", "Aspirin", "Bayer", "few"],
+    ]);
+    let response = upload(&client, "/api/stock-out-from-xlsx", &file).await;
+    assert_eq!(response.status(), Status::Ok);
+    let report: Value = response.into_json().await.unwrap();
+    assert_eq!(report["dry_run"], false);
+    assert_eq!(report["committed"], false);
+    assert_eq!(report["rows"][0]["row"], 2);
+    assert_eq!(report["rows"].as_array().unwrap().len(), 1);
+    let columns: Vec<(&Value, &Value)> = report["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| (&error["row"], &error["column"]))
+        .collect();
+    assert_eq!(
+        columns,
+        [
+            (&json!(3), &Value::Null),
+            (&json!(4), &json!("number")),
+            (&json!(5), &Value::Null),
+            (&json!(7), &json!("date")),
+            (&json!(7), &json!("number")),
+        ]
+    );
+
+    let file = xlsx(&[HEADER, &["44927", "Aspirin", "Bayer", "5", "order-1"]]);
+    for dry_run in [true, false] {
+        let report: Value = upload(
+            &client,
+            &format!("/api/stock-out-from-xlsx?dry_run={}", dry_run),
+            &file,
+        )
+        .await
+        .into_json()
+        .await
+        .unwrap();
+        assert_eq!(report["dry_run"], dry_run);
+        assert_eq!(report["committed"], !dry_run);
+        assert_eq!(report["errors"], json!([]));
+        assert_eq!(report["rows"][0]["item_id"], item_id);
+        assert_eq!(report["rows"][0]["number"], 5);
+    }
+
+    let stock_outs: Value = client
+        .get(format!("/api/items/{}/stock-outs", item_id))
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(stock_outs.as_array().unwrap().len(), 1);
+    assert_eq!(stock_outs[0]["date"], "2023-01-01");
+    assert_eq!(stock_outs[0]["reference"], "order-1");
+}
+
+#[rocket::async_test]
+async fn batch_import_reports_rows_and_supports_dry_run() {
+    let client = client().await;
+    let item_id = create_item(&client).await;
+
+    const HEADER: &[&str] = &[
+        "date",
+        "name",
+        "spec",
+        "unit",
+        "manufacturer",
+        "number",
+        "price",
+        "expiration",
+        "vendor",
+    ];
+    const ASPIRIN: &[&str] = &[
+        "44927", "Aspirin", "100mg", "box", "Bayer", "10", "1.5", "45658", "Acme",
+    ];
+    const IBUPROFEN: &[&str] = &[
+        "44927",
+        "Ibuprofen",
+        "200mg",
+        "box",
+        "Advil",
+        "4",
+        "2",
+        "45658",
+        "Acme",
+    ];
+    let import = |uri: &'static str, file: Vec<u8>| {
+        let client = &client;
+        async move {
+            upload(client, uri, &file)
+                .await
+                .into_json::<Value>()
+                .await
+                .unwrap()
+        }
+    };
+
+    let report = import(
+        "/api/batches-from-xlsx",
+        xlsx(&[
+            HEADER,
+            ASPIRIN,
+            &["x", "", "", "", "Bayer", "-1", "cheap", "45658", ""],
+            &["", "", "", "", "", "", "", "", ""],
+            &["44927", "Ibuprofen", "", "", " ", "2", "inf", "45658", ""],
+        ]),
+    )
+    .await;
+    assert_eq!(report["committed"], false);
+    let columns: Vec<(&Value, &Value)> = report["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| (&error["row"], &error["column"]))
+        .collect();
+    assert_eq!(
+        columns,
+        [
+            (&json!(3), &json!("date")),
+            (&json!(3), &json!("name")),
+            (&json!(3), &json!("number")),
+            (&json!(3), &json!("price")),
+            (&json!(5), &json!("manufacturer")),
+            (&json!(5), &json!("price")),
+        ]
+    );
+
+    // Blank rows are skipped in every format.
+    const BLANK: &[&str] = &["", " ", "", "", "", "", "", "", ""];
+    let csv = [HEADER, ASPIRIN, BLANK, ASPIRIN]
+        .iter()
+        .map(|row| row.join(",") + "\n")
+        .collect::<String>()
+        .into_bytes();
+    for file in [
+        xlsx(&[HEADER, ASPIRIN, BLANK, ASPIRIN]),
+        ods(&[HEADER, ASPIRIN, BLANK, ASPIRIN]),
+        csv,
+    ] {
+        let report = import("/api/batches-from-xlsx?dry_run=true", file).await;
+        assert_eq!(report["errors"], json!([]));
+        assert_eq!(report["batches_created"], 2);
+    }
+
+    let file = xlsx(&[HEADER, ASPIRIN, IBUPROFEN, IBUPROFEN]);
+    let report = import("/api/batches-from-xlsx?dry_run=true", file.clone()).await;
+    assert_eq!(report["dry_run"], true);
+    assert_eq!(report["committed"], false);
+    assert_eq!(report["batches_created"], 3);
+    assert_eq!(report["items_created"], 1);
+    assert_eq!(report["rows"][0]["item_id"], item_id);
+    assert_eq!(report["rows"][0]["new_item"], false);
+    assert_eq!(report["rows"][1]["new_item"], true);
+    assert_eq!(report["rows"][2]["new_item"], false);
+
+    let page: Value = client
+        .get("/api/items")
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(page["total"], 1);
+
+    let report = import("/api/batches-from-xlsx", file).await;
+    assert_eq!(report["committed"], true);
+    assert_eq!(report["batches_created"], 3);
+    assert_eq!(report["items_created"], 1);
+
+    let page: Value = client
+        .get("/api/items?sort=name")
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(page["total"], 2);
+    assert_eq!(page["items"][0]["number"], 10);
+    assert_eq!(page["items"][1]["number"], 8);
+}
+
+#[rocket::async_test]
+async fn import_columns_are_found_by_header() {
+    let figment = Config::figment()
+        .merge(("import.aliases.vendor", ["Lieferant"]))
+        .merge((
+            "import.profiles.acme",
+            json!({ "name": "Product", "number": "Units" }),
+        ));
+    let client = client_with(figment).await;
+    let items = || async {
+        let page: Value = client
+            .get("/api/items?sort=name")
+            .dispatch()
+            .await
+            .into_json()
+            .await
+            .unwrap();
+        page["items"].as_array().unwrap().clone()
+    };
+    let import = |uri: &'static str, file: Vec<u8>| {
+        let client = &client;
+        async move { upload(client, uri, &file).await }
+    };
+
+    // Reordered Chinese headers with an extra column and a configured alias.
+    let file = xlsx(&[
+        &[
+            "名称",
+            "备注",
+            "数量",
+            "生产厂家",
+            "有效期",
+            "单价",
+            "日期",
+            "Lieferant",
+        ],
+        &[
+            "Aspirin", "-", "10", "Bayer", "45658", "1.5", "44927", "Acme",
+        ],
+    ]);
+    let response = import("/api/batches-from-xlsx", file).await;
+    assert_eq!(response.status(), Status::Ok);
+    let item = &items().await[0];
+    assert_eq!(item["name"], "Aspirin");
+    assert_eq!(item["number"], 10);
+    let batches: Value = client
+        .get("/api/batches-and-items")
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(batches["items"][0]["vendor"], "Acme");
+    assert_eq!(batches["items"][0]["date"], "2023-01-01");
+
+    let file = xlsx(&[
+        &["Date", "Product", "Manufacturer", "Units"],
+        &["44927", "Aspirin", "Bayer", "4"],
+    ]);
+    let response = import("/api/stock-out-from-xlsx?profile=acme", file.clone()).await;
+    let report: Value = response.into_json().await.unwrap();
+    assert_eq!(report["committed"], true);
+
+    let response = import("/api/stock-out-from-xlsx?profile=other", file.clone()).await;
+    assert_eq!(response.status(), Status::BadRequest);
+    let response = import("/api/stock-out-from-xlsx", file).await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    // Header rows naming no known column are read in the original fixed order.
+    let file = xlsx(&[&["A", "B", "C", "D"], &["44927", "Aspirin", "Bayer", "1"]]);
+    let report: Value = import("/api/stock-out-from-xlsx", file)
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(report["committed"], true);
+    assert_eq!(items().await[0]["number"], 5);
+}
+
+/// Switches an xlsx workbook to the 1904 date system.
+fn with_1904_dates(file: &[u8]) -> Vec<u8> {
+    use std::io::{Read, Write};
+
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(file)).unwrap();
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).unwrap();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content).unwrap();
+        if entry.name() == "xl/workbook.xml" {
+            let xml = String::from_utf8(content).unwrap();
+            content = xml
+                .replacen("<workbookPr", "<workbookPr date1904=\"1\"", 1)
+                .into_bytes();
+        }
+        writer
+            .start_file(entry.name(), zip::write::FileOptions::default())
+            .unwrap();
+        writer.write_all(&content).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+#[rocket::async_test]
+async fn import_dates_follow_the_workbook() {
+    let client = client().await;
+    let item_id = create_item(&client).await;
+    create_batch(&client, item_id, 10, "2025-01-01", false).await;
+
+    const HEADER: &[&str] = &["date", "name", "manufacturer", "number"];
+    for (file, expected) in [
+        (
+            xlsx(&[HEADER, &["2024/3/1", "Aspirin", "Bayer", "1"]]),
+            "2024-03-01",
+        ),
+        (
+            xlsx(&[HEADER, &["45353.5", "Aspirin", "Bayer", "1"]]),
+            "2024-03-02",
+        ),
+        (
+            with_1904_dates(&xlsx(&[HEADER, &["43892", "Aspirin", "Bayer", "1"]])),
+            "2024-03-03",
+        ),
+    ] {
+        let report: Value = upload(&client, "/api/stock-out-from-xlsx", &file)
+            .await
+            .into_json()
+            .await
+            .unwrap();
+        assert_eq!(report["committed"], true, "{}", report);
+        let stock_outs: Value = client
+            .get(format!("/api/items/{}/stock-outs", item_id))
+            .dispatch()
+            .await
+            .into_json()
+            .await
+            .unwrap();
+        assert_eq!(stock_outs[0]["date"], expected);
+    }
+}
+
+#[rocket::async_test]
+async fn batches_are_imported_from_csv_and_ods() {
+    let client = client().await;
+
+    let utf8 = "\u{feff}date,name,manufacturer,number,price,expiration\n\
+        2024-03-01,Aspirin,Bayer,10,1.5,2026-01-01\n"
+        .as_bytes()
+        .to_vec();
+    let (gbk, _, _) = encoding_rs::GBK.encode(
+        "日期;名称;规格;生产厂家;数量;单价;有效期\n2024/3/2;阿司匹林;100mg;拜耳;5;1.5;2026/1/1\n",
+    );
+    let spreadsheet = ods(&[
+        &[
+            "date",
+            "name",
+            "manufacturer",
+            "number",
+            "price",
+            "expiration",
+        ],
+        &["2024-03-03", "Ibuprofen", "Advil", "7", "2", "2026-01-01"],
+    ]);
+
+    for (filename, content_type, file, items_created) in [
+        ("items.csv", "application/octet-stream", utf8, 1),
+        ("export", "application/octet-stream", gbk.into_owned(), 1),
+        (
+            "items",
+            "application/vnd.oasis.opendocument.spreadsheet",
+            spreadsheet.clone(),
+            1,
+        ),
+        // Without a name or type the ods is told apart from xlsx by content.
+        ("upload", "application/octet-stream", spreadsheet, 0),
+    ] {
+        let response = upload_named(
+            &client,
+            "/api/batches-from-xlsx",
+            filename,
+            content_type,
+            &file,
+        )
+        .await;
+        assert_eq!(response.status(), Status::Ok, "{}", filename);
+        let report: Value = response.into_json().await.unwrap();
+        assert_eq!(report["committed"], true, "{} {}", filename, report);
+        assert_eq!(report["batches_created"], 1, "{}", filename);
+        assert_eq!(report["items_created"], items_created, "{}", filename);
+    }
+
+    let page: Value = client
+        .get("/api/batches-and-items?sort=date&order=asc")
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    let batches: Vec<(&Value, &Value, &Value)> = page["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|batch| (&batch["date"], &batch["name"], &batch["number"]))
+        .collect();
+    assert_eq!(
+        batches,
+        [
+            (&json!("2024-03-01"), &json!("Aspirin"), &json!(10)),
+            (&json!("2024-03-02"), &json!("阿司匹林"), &json!(5)),
+            (&json!("2024-03-03"), &json!("Ibuprofen"), &json!(7)),
+            (&json!("2024-03-03"), &json!("Ibuprofen"), &json!(7)),
+        ]
+    );
+}
+
+#[rocket::async_test]
+async fn exports_round_trip_through_the_importer() {
+    let client = client().await;
+    let item_id = create_item(&client).await;
+    create_batch(&client, item_id, 10, "2026-01-01", false).await;
+
+    let response = client.get("/api/items/export?format=csv").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Content-Disposition"),
+        Some("attachment; filename=\"items.csv\"")
+    );
+    assert_eq!(
+        response.into_string().await.unwrap(),
+        "\u{feff}id,name,specification,unit,manufacturer,number,price,expiration,sku\n\
+        1,Aspirin,100mg,box,Bayer,10,1,2026-01-01,\n"
+    );
+
+    let response = client
+        .get("/api/stock-in-and-items/export?from=2023-01-01&to=2023-12-31&format=csv")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.into_string().await.unwrap(),
+        "\u{feff}item_id,name,specification,unit,manufacturer,price,number\n\
+        1,Aspirin,100mg,box,Bayer,1,10\n"
+    );
+
+    let response = client
+        .get("/api/stock-out-and-items/export?from=2023-01-01&to=2023-12-31")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Content-Disposition"),
+        Some("attachment; filename=\"stock-out-2023-01-01-2023-12-31.xlsx\"")
+    );
+
+    let response = client.get("/api/items/export?format=pdf").dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    // Exported batches import again as the same batches of the same item.
+    for format in ["xlsx", "csv"] {
+        let response = client
+            .get(format!("/api/batches-and-items/export?format={}", format))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let file = response.into_bytes().await.unwrap();
+
+        let response = upload_named(
+            &client,
+            "/api/batches-from-xlsx?dry_run=true",
+            &format!("batches.{}", format),
+            "application/octet-stream",
+            &file,
+        )
+        .await;
+        let report: Value = response.into_json().await.unwrap();
+        assert_eq!(report["errors"], json!([]), "{}", format);
+        assert_eq!(report["batches_created"], 1, "{}", format);
+        assert_eq!(report["rows"][0]["item_id"], item_id, "{}", format);
+        assert_eq!(report["rows"][0]["new_item"], false, "{}", format);
+    }
+
+    let spreadsheet = client
+        .get("/api/batches-and-items/export")
+        .dispatch()
+        .await
+        .into_bytes()
+        .await
+        .unwrap();
+    let book = umya_spreadsheet::reader::xlsx::read_reader(std::io::Cursor::new(spreadsheet), true)
+        .unwrap();
+    let sheet = book.get_sheet(&0).unwrap();
+    assert_eq!(sheet.get_value("B1"), "date");
+    assert_eq!(sheet.get_formatted_value("B2"), "2023-01-01");
+    assert_eq!(sheet.get_formatted_value("I2"), "2026-01-01");
+}
+
+#[rocket::async_test]
+async fn exports_do_not_write_formulas() {
+    let client = client().await;
+    let response = client
+        .post("/api/items")
+        .json(&json!({
+            "name": "=1+1", "specification": "-5", "unit": "\tbox",
+            "manufacturer": "@Bayer", "price": 1.0
+        }))
+        .dispatch()
+        .await;
+    let item_id: i32 = response.into_json().await.unwrap();
+    create_batch(&client, item_id, 10, "2026-01-01", false).await;
+
+    let response = client.get("/api/items/export?format=csv").dispatch().await;
+    assert_eq!(
+        response.into_string().await.unwrap(),
+        "\u{feff}id,name,specification,unit,manufacturer,number,price,expiration,sku\n\
+        1,'=1+1,'-5,'\tbox,'@Bayer,10,1,2026-01-01,\n"
+    );
+
+    let spreadsheet = client
+        .get("/api/items/export")
+        .dispatch()
+        .await
+        .into_bytes()
+        .await
+        .unwrap();
+    let book = umya_spreadsheet::reader::xlsx::read_reader(std::io::Cursor::new(spreadsheet), true)
+        .unwrap();
+    let cell = book.get_sheet(&0).unwrap().get_cell("B2").unwrap();
+    assert_eq!(cell.get_value(), "=1+1");
+    assert_eq!(cell.get_formula(), "");
+
+    // The quote is dropped again when the export is imported.
+    let file = client
+        .get("/api/batches-and-items/export?format=csv")
+        .dispatch()
+        .await
+        .into_bytes()
+        .await
+        .unwrap();
+    let response = upload_named(
+        &client,
+        "/api/batches-from-xlsx?dry_run=true",
+        "batches.csv",
+        "text/csv",
+        &file,
+    )
+    .await;
+    let report: Value = response.into_json().await.unwrap();
+    assert_eq!(report["errors"], json!([]));
+    assert_eq!(report["rows"][0]["item_id"], item_id);
+    assert_eq!(report["rows"][0]["new_item"], false);
+}
+
+#[rocket::async_test]
+async fn oversized_uploads_are_refused() {
+    let file = |padding: usize| {
+        let mut file = b"date,name,manufacturer,number,price,expiration\n\
+            2024-03-01,Aspirin,Bayer,10,1.5,2026-01-01\n"
+            .to_vec();
+        file.resize(file.len() + padding, b'\n');
+        file
+    };
+
+    // Larger than Rocket's default form and file limits, within ours.
+    let client = client().await;
+    let response = upload(
+        &client,
+        "/api/batches-from-xlsx?dry_run=true",
+        &file(3 << 20),
+    )
+    .await;
+    assert_eq!(response.status(), Status::Ok);
+    let report: Value = response.into_json().await.unwrap();
+    assert_eq!(report["batches_created"], 1);
+
+    let client = client_with(Config::figment().merge(("import.max_upload_size", "1 KiB"))).await;
+    let response = upload(&client, "/api/batches-from-xlsx?dry_run=true", &file(0)).await;
+    assert_eq!(response.status(), Status::Ok);
+    for uri in ["/api/batches-from-xlsx", "/api/stock-out-from-xlsx"] {
+        let response = upload(&client, uri, &file(2048)).await;
+        assert_eq!(response.status(), Status::PayloadTooLarge);
+        let body: Value = response.into_json().await.unwrap();
+        assert_eq!(body["error"]["code"], 413);
+    }
+}
+
+#[rocket::async_test]
+async fn sparse_sheets_are_read_cell_by_cell() {
+    // A blank cell in the last row and column Excel allows.
+    let mut book = umya_spreadsheet::reader::xlsx::read_reader(
+        std::io::Cursor::new(xlsx(&[
+            &[
+                "date",
+                "name",
+                "manufacturer",
+                "number",
+                "price",
+                "expiration",
+            ],
+            &["44927", "Aspirin", "Bayer", "10", "1.5", "45658"],
+        ])),
+        true,
+    )
+    .unwrap();
+    book.get_sheet_mut(&0)
+        .unwrap()
+        .get_cell_mut("XFD1048576")
+        .set_value(" ");
+    let mut file = std::io::Cursor::new(Vec::new());
+    umya_spreadsheet::writer::xlsx::write_writer(&book, &mut file).unwrap();
+
+    let client = client().await;
+    let response = upload(
+        &client,
+        "/api/batches-from-xlsx?dry_run=true",
+        &file.into_inner(),
+    )
+    .await;
+    assert_eq!(response.status(), Status::Ok);
+    let report: Value = response.into_json().await.unwrap();
+    assert_eq!(report["errors"], json!([]));
+    assert_eq!(report["batches_created"], 1);
+}
+
+#[rocket::async_test]
+async fn imports_match_items_by_sku_and_specification() {
+    let client = client().await;
+    let mut ids = Vec::new();
+    for (specification, sku) in [("100mg", "6901234567892"), ("500mg", "")] {
+        let id: i32 = client
+            .post("/api/items")
+            .json(&json!({
+                "name": "Aspirin", "specification": specification, "unit": "box",
+                "manufacturer": "Bayer", "number": 0, "price": 1.0,
+                "expiration": "2099-12-31", "sku": sku
+            }))
+            .dispatch()
+            .await
+            .into_json()
+            .await
+            .unwrap();
+        ids.push(id);
+    }
+
+    let response = client
+        .post("/api/items")
+        .json(&json!({
+            "name": "Ibuprofen", "specification": null, "unit": null,
+            "manufacturer": "Advil", "number": 0, "price": 1.0,
+            "expiration": "2099-12-31", "sku": "6901234567892"
+        }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Conflict);
+
+    let file = "date,name,specification,manufacturer,number,price,expiration,barcode\n\
+        2024-03-01,Aspirin,,Bayer,1,1,2026-01-01,6901234567892\n\
+        2024-03-01,Aspirin,500mg,Bayer,1,1,2026-01-01,\n\
+        2024-03-01,Aspirin,,Bayer,1,1,2026-01-01,\n\
+        2024-03-01,Aspirin,,Bayer,1,1,2026-01-01,6900000000000\n";
+    let report: Value = upload(
+        &client,
+        "/api/batches-from-xlsx?dry_run=true",
+        file.as_bytes(),
+    )
+    .await
+    .into_json()
+    .await
+    .unwrap();
+    let rows: Vec<(&Value, &Value)> = report["rows"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| (&row["item_id"], &row["new_item"]))
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            (&json!(ids[0]), &json!(false)),
+            (&json!(ids[1]), &json!(false)),
+            // An unknown SKU only matches items without one.
+            (&json!(ids[1]), &json!(false)),
+        ]
+    );
+    assert_eq!(report["errors"].as_array().unwrap().len(), 1);
+    assert_eq!(report["errors"][0]["row"], 4);
+    assert_eq!(
+        report["errors"][0]["message"],
+        "2 items match 'Aspirin' from 'Bayer'; give a SKU or specification to tell them apart."
+    );
+
+    // An archived item's SKU is reported, not taken for a new item.
+    let response = client
+        .post(format!("/api/items/{}/archive", ids[0]))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let file = "date,name,manufacturer,number,price,expiration,barcode\n\
+        2024-03-01,Aspirin,Bayer,1,1,2026-01-01,6901234567892\n";
+    let response = upload(&client, "/api/batches-from-xlsx", file.as_bytes()).await;
+    assert_eq!(response.status(), Status::Ok);
+    let report: Value = response.into_json().await.unwrap();
+    assert_eq!(
+        report["errors"][0]["message"],
+        format!(
+            "Item {} with SKU '6901234567892' is archived; restore it first.",
+            ids[0]
+        )
+    );
+}
+
+#[rocket::async_test]
+async fn session_cookie_is_secure_unless_configured() {
+    for (figment, secure) in [
+        (Config::figment(), true),
+        (
+            Config::figment().merge(("session.secure_cookie", false)),
+            false,
+        ),
+    ] {
+        let client = client_with(figment).await;
+        let response = client
+            .post("/api/login")
+            .json(&json!({ "username": "manager", "password": PASSWORD }))
+            .dispatch()
+            .await;
+        let cookie = response.cookies().get("session").unwrap();
+        assert_eq!(cookie.secure().unwrap_or(false), secure);
+    }
+
+    let client = client().await;
+    let response = client
+        .post("/api/login")
+        .json(&json!({ "username": "nobody", "password": PASSWORD }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn routes_require_a_session_and_role() {
+    let client = Client::untracked(crate::rocket(Config::figment(), database().await))
+        .await
+        .unwrap();
+    let log_in = |username: &'static str, password: &'static str| {
+        let client = &client;
+        async move {
+            let response = client
+                .post("/api/login")
+                .json(&json!({ "username": username, "password": password }))
+                .dispatch()
+                .await;
+            let status = response.status();
+            let login: Option<Value> = response.into_json().await;
+            (
+                status,
+                login.and_then(|login| login["token"].as_str().map(String::from)),
+            )
+        }
+    };
+    let bearer = |token: &str| Header::new("Authorization", format!("Bearer {}", token));
+
+    let response = client.get("/api/items").dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["error"]["code"], 401);
+    assert_eq!(
+        log_in("manager", "wrong password").await.0,
+        Status::Unauthorized
+    );
+    assert_eq!(log_in("nobody", PASSWORD).await.0, Status::Unauthorized);
+
+    let manager = log_in("manager", PASSWORD).await.1.unwrap();
+    for (username, role) in [("viewer", "viewer"), ("clerk", "clerk")] {
+        let response = client
+            .post("/api/users")
+            .header(bearer(&manager))
+            .json(&json!({ "username": username, "password": PASSWORD, "role": role }))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+    }
+    let response = client
+        .post("/api/users")
+        .header(bearer(&manager))
+        .json(&json!({ "username": "short", "password": "short", "role": "viewer" }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let viewer = log_in("viewer", PASSWORD).await.1.unwrap();
+    let clerk = log_in("clerk", PASSWORD).await.1.unwrap();
+    let item = json!({
+        "name": "Aspirin", "specification": null, "unit": null, "manufacturer": "Bayer",
+        "number": 0, "price": 1.0, "expiration": "2099-12-31"
+    });
+
+    let response = client
+        .get("/api/items")
+        .header(bearer(&viewer))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let response = client
+        .post("/api/items")
+        .header(bearer(&viewer))
+        .json(&item)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["error"]["code"], 403);
+
+    let item_id: i32 = client
+        .post("/api/items")
+        .header(bearer(&clerk))
+        .json(&item)
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    let response = client
+        .post("/api/stock-out?allow_negative=true&reason=recount")
+        .header(bearer(&clerk))
+        .json(&json!({ "id": 0, "date": "2024-03-01", "number": 1, "item_id": item_id, "operator": "someone else" }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let stock_outs: Value = client
+        .get(format!("/api/items/{}/stock-outs", item_id))
+        .header(bearer(&clerk))
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(stock_outs[0]["operator"], "clerk");
+
+    let delete = format!("/api/items/{}", item_id);
+    let response = client
+        .delete(delete.clone())
+        .header(bearer(&clerk))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+    let response = client
+        .get("/api/users")
+        .header(bearer(&clerk))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let me: Value = client
+        .get("/api/me")
+        .header(bearer(&clerk))
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(me["username"], "clerk");
+    assert_eq!(me["role"], "clerk");
+    assert!(me.get("password_hash").is_none());
+
+    let response = client
+        .post("/api/logout")
+        .header(bearer(&clerk))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let response = client
+        .get("/api/me")
+        .header(bearer(&clerk))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let spec: Value = client
+        .get("/api/openapi.json")
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(
+        spec["components"]["securitySchemes"]["session"]["scheme"],
+        "bearer"
+    );
+}
+
+#[rocket::async_test]
+async fn mutations_are_audited() {
+    let client = client().await;
+    let item_id = create_item(&client).await;
+    create_batch(&client, item_id, 10, "2025-01-01", false).await;
+
+    let response = client
+        .put(format!("/api/items/{}", item_id))
+        .header(Header::new("X-Request-Id", "edit-price-1"))
+        .json(&json!({
+            "id": item_id, "name": "Aspirin", "specification": "100mg", "unit": "box",
+            "manufacturer": "Bayer", "number": 10, "price": 2.5, "expiration": "2025-01-01"
+        }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("X-Request-Id"),
+        Some("edit-price-1")
+    );
+
+    let stock_out_id: i32 = client
+        .post("/api/stock-out")
+        .json(&json!({ "id": 0, "date": "2024-03-01", "number": 4, "item_id": item_id }))
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    let response = client
+        .delete(format!("/api/stock-out/{}", stock_out_id))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    // A refused change leaves no entry.
+    let response = client
+        .post("/api/stock-out")
+        .json(&json!({ "id": 0, "date": "2024-03-01", "number": 99, "item_id": item_id }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Conflict);
+
+    let audit: Value = client
+        .get("/api/audit")
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(audit["total"], 5);
+    let entries: Vec<(&str, &str)> = audit["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| (e["entity"].as_str().unwrap(), e["action"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            ("stock_out", "reverse"),
+            ("stock_out", "create"),
+            ("item", "update"),
+            ("batch", "create"),
+            ("item", "create"),
+        ]
+    );
+    let edit = &audit["items"][2];
+    assert_eq!(edit["actor"], "manager");
+    assert_eq!(edit["request_id"], "edit-price-1");
+    assert_eq!(edit["before"]["price"], 1.0);
+    assert_eq!(edit["after"]["price"], 2.5);
+    assert!(audit["items"][4]["before"].is_null());
+    assert!(audit["items"][0]["before"]["reversed_at"].is_null());
+    assert!(audit["items"][0]["after"]["reversed_at"].is_string());
+
+    let audit: Value = client
+        .get(format!(
+            "/api/audit?entity=item&entity_id={}&actor=manager",
+            item_id
+        ))
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(audit["total"], 2);
+    let today = chrono::Local::now().naive_local().date();
+    let audit: Value = client
+        .get(format!("/api/audit?from={}", today.succ()))
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(audit["total"], 0);
+    let response = client.get("/api/audit?to=%2B262143-12-31").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let response = client.get("/api/audit?entity=user").dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn items_with_history_are_archived_not_deleted() {
+    let client = client().await;
+    let item_id = create_item(&client).await;
+    create_batch(&client, item_id, 10, "2025-01-01", false).await;
+    let item = format!("/api/items/{}", item_id);
+    let listed = |archived: bool| {
+        let client = &client;
+        async move {
+            let items: Value = client
+                .get(format!("/api/items?archived={}", archived))
+                .dispatch()
+                .await
+                .into_json()
+                .await
+                .unwrap();
+            items["total"].as_u64().unwrap()
+        }
+    };
+
+    let response = client.delete(item.clone()).dispatch().await;
+    assert_eq!(response.status(), Status::Conflict);
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(
+        body["error"]["description"],
+        format!(
+            "Item {} has 1 batches, 0 stock-outs and 0 stock adjustments; \
+            archive it instead, or purge them with it.",
+            item_id
+        )
+    );
+
+    let response = client.post(format!("{}/archive", item)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!((listed(false).await, listed(true).await), (0, 1));
+    let report: Value = client
+        .get("/api/stock-in-and-items?from=2023-01-01&to=2023-01-31")
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(report[0]["number"], 10);
+    let file = xlsx(&[
+        &["date", "name", "manufacturer", "number"],
+        &["44927", "Aspirin", "Bayer", "1"],
+    ]);
+    let report: Value = upload(&client, "/api/stock-out-from-xlsx", &file)
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(
+        report["errors"][0]["message"],
+        "No item matches 'Aspirin' from 'Bayer'."
+    );
+
+    let response = client.post(format!("{}/restore", item)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!((listed(false).await, listed(true).await), (1, 0));
+
+    let response = client
+        .delete(format!("/api/items/{}", create_item(&client).await))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let response = client
+        .delete(format!("{}?purge=true", item))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let response = client.get("/api/batches-and-items").dispatch().await;
+    let batches: Value = response.into_json().await.unwrap();
+    assert_eq!(batches["total"], 0);
+
+    let audit: Value = client
+        .get(format!("/api/audit?entity=item&entity_id={}", item_id))
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    let actions: Vec<&str> = audit["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(actions, ["delete", "restore", "archive", "create"]);
+
+    // The purged batch is logged as deleted too, with what it held.
+    let audit: Value = client
+        .get("/api/audit?entity=batch")
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(audit["items"][0]["action"], "delete");
+    assert_eq!(audit["items"][0]["before"]["remaining"], 10);
+    assert!(audit["items"][0]["after"].is_null());
+}
+
+#[rocket::async_test]
+async fn item_edits_leave_stock_to_adjustments() {
+    let client = client().await;
+    let item_id = create_item(&client).await;
+    create_batch(&client, item_id, 10, "2025-01-01", false).await;
+    let item = || {
+        let client = &client;
+        async move {
+            let items: Value = client
+                .get("/api/items")
+                .dispatch()
+                .await
+                .into_json()
+                .await
+                .unwrap();
+            items["items"][0].clone()
+        }
+    };
+
+    let response = client
+        .put(format!("/api/items/{}", item_id))
+        .json(&json!({
+            "id": item_id, "name": "Aspirin Protect", "specification": "100mg", "unit": "box",
+            "manufacturer": "Bayer", "number": 999, "price": 1.5, "expiration": "2000-01-01"
+        }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let edited = item().await;
+    assert_eq!(edited["name"], "Aspirin Protect");
+    assert_eq!(edited["price"], 1.5);
+    assert_eq!(edited["number"], 10);
+    assert_eq!(edited["expiration"], "2025-01-01");
+
+    let adjust = |body: Value| {
+        let client = &client;
+        async move {
+            client
+                .post(format!("/api/items/{}/stock-adjustments", item_id))
+                .json(&body)
+                .dispatch()
+                .await
+        }
+    };
+    let response = adjust(json!({ "delta": -3, "reason": "broken in storage" })).await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(item().await["number"], 7);
+    // Added stock must say which enabled batch of the item it belongs to.
+    let response = adjust(json!({ "counted": 12, "reason": "stocktake" })).await;
+    assert_eq!(response.status(), Status::BadRequest);
+    let response = adjust(json!({ "counted": 12, "batch_id": 42, "reason": "stocktake" })).await;
+    assert_eq!(response.status(), Status::BadRequest);
+    let response = adjust(json!({ "counted": 12, "batch_id": 1, "reason": "stocktake" })).await;
+    assert_eq!(response.status(), Status::Ok);
+    let adjustment: Value = response.into_json().await.unwrap();
+    assert_eq!(
+        (&adjustment["previous"], &adjustment["delta"]),
+        (&json!(7), &json!(5))
+    );
+    assert_eq!(item().await["number"], 12);
+
+    let response = adjust(json!({ "delta": -20, "reason": "lost" })).await;
+    assert_eq!(response.status(), Status::Conflict);
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["error"]["available"], 12);
+    for body in [
+        json!({ "reason": "nothing" }),
+        json!({ "delta": 1, "counted": 1, "reason": "both" }),
+        json!({ "delta": 0, "reason": "zero" }),
+        json!({ "counted": -1, "reason": "negative" }),
+        json!({ "delta": 1, "reason": " " }),
+    ] {
+        assert_eq!(adjust(body).await.status(), Status::BadRequest);
+    }
+
+    let adjustments: Value = client
+        .get(format!("/api/items/{}/stock-adjustments", item_id))
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    let deltas: Vec<(&str, i64)> = adjustments
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|a| (a["reason"].as_str().unwrap(), a["delta"].as_i64().unwrap()))
+        .collect();
+    assert_eq!(deltas, [("stocktake", 5), ("broken in storage", -3)]);
+    assert_eq!(adjustments[0]["operator"], "manager");
+
+    // Taken stock comes from the batches expiring first.
+    create_batch(&client, item_id, 5, "2026-01-01", false).await;
+    let response = adjust(json!({ "delta": -14, "reason": "flood" })).await;
+    assert_eq!(response.status(), Status::Ok);
+    let adjustment: Value = response.into_json().await.unwrap();
+    let batches: Value = client
+        .get(format!(
+            "/api/stock-adjustments/{}/batches",
+            adjustment["id"]
+        ))
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    let numbers: Vec<(i64, i64)> = batches
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|b| {
+            (
+                b["batch_id"].as_i64().unwrap(),
+                b["number"].as_i64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(numbers, [(1, -12), (2, -2)]);
+    let item = item().await;
+    assert_eq!(item["number"], 3);
+    assert_eq!(item["expiration"], "2026-01-01");
+
+    // New items start without stock, whatever the body says.
+    let id: i32 = client
+        .post("/api/items")
+        .json(&json!({
+            "name": "Ibuprofen", "manufacturer": "Bayer", "price": 2.0,
+            "number": 50, "expiration": "2000-01-01"
+        }))
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    let created: Value = client
+        .get(format!("/api/items/{}", id))
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(created["number"], 0);
+    assert_eq!(created["expiration"], "2099-12-31");
+}
+
+#[rocket::async_test]
+async fn items_and_batches_honour_if_match() {
+    let client = client().await;
+    let item_id = create_item(&client).await;
+    let etag = |response: &LocalResponse<'_>| response.headers().get_one("ETag").map(String::from);
+
+    let response = client
+        .get(format!("/api/items/{}", item_id))
+        .dispatch()
+        .await;
+    assert_eq!(etag(&response).as_deref(), Some("\"1\""));
+    let item: Value = response.into_json().await.unwrap();
+    assert_eq!(item["version"], 1);
+
+    // Receiving a batch changes the item's stock, and so its version.
+    create_batch(&client, item_id, 10, "2025-01-01", false).await;
+    let details = json!({
+        "name": "Aspirin Protect", "specification": "100mg", "unit": "box",
+        "manufacturer": "Bayer", "price": 1.5
+    });
+    let edit = |if_match: &'static str| {
+        let (client, details) = (&client, &details);
+        async move {
+            client
+                .put(format!("/api/items/{}", item_id))
+                .header(Header::new("If-Match", if_match))
+                .json(details)
+                .dispatch()
+                .await
+        }
+    };
+    let response = edit("\"1\"").await;
+    assert_eq!(response.status(), Status::PreconditionFailed);
+    assert_eq!(edit("one").await.status(), Status::BadRequest);
+    assert_eq!(edit("W/\"2\"").await.status(), Status::BadRequest);
+    let response = edit("\"2\"").await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(etag(&response).as_deref(), Some("\"3\""));
+    let response = edit("*").await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(etag(&response).as_deref(), Some("\"4\""));
+
+    let response = client
+        .post(format!("/api/items/{}/stock-adjustments", item_id))
+        .header(Header::new("If-Match", "\"3\""))
+        .json(&json!({ "counted": 8, "reason": "stocktake" }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::PreconditionFailed);
+    let response = client
+        .post(format!("/api/items/{}/archive", item_id))
+        .header(Header::new("If-Match", "\"4\""))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(etag(&response).as_deref(), Some("\"5\""));
+
+    let batches: Value = client
+        .get("/api/batches-and-items")
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    let batch_id = batches["items"][0]["id"].as_i64().unwrap();
+    assert_eq!(batches["items"][0]["version"], 1);
+    let patch = |if_match: &'static str| {
+        let client = &client;
+        async move {
+            client
+                .patch(format!("/api/batches/{}", batch_id))
+                .header(Header::new("If-Match", if_match))
+                .json(&json!({ "vendor": "Acme" }))
+                .dispatch()
+                .await
+        }
+    };
+    let response = patch("\"1\"").await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(etag(&response).as_deref(), Some("\"2\""));
+    let response = patch("\"1\"").await;
+    assert_eq!(response.status(), Status::PreconditionFailed);
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["error"]["code"], 412);
+
+    let response = client
+        .get(format!("/api/batches/{}", batch_id))
+        .dispatch()
+        .await;
+    assert_eq!(etag(&response).as_deref(), Some("\"2\""));
+    let batch: Value = response.into_json().await.unwrap();
+    assert_eq!(batch["vendor"], "Acme");
+}